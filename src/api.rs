@@ -0,0 +1,138 @@
+use std::future::Future;
+
+use crate::{
+    DeleteImageResponse, GyazoClient, GyazoError, GyazoImageResponse, ImagePage, ListImagesParams,
+    OembedResponse, UploadImageResponse, UploadParams, UserResponse,
+};
+
+/// Operations offered by the Gyazo API
+///
+/// Implemented by [`GyazoClient`] and by [`crate::FakeGyazo`], so code written
+/// against this trait can be unit tested without a network connection.
+pub trait GyazoApi {
+    /// Get an image by its ID
+    fn get_image(
+        &self,
+        image_id: &str,
+    ) -> impl Future<Output = Result<GyazoImageResponse, GyazoError>> + Send;
+
+    /// Get a list of images
+    fn list_images(
+        &self,
+    ) -> impl Future<Output = Result<Vec<GyazoImageResponse>, GyazoError>> + Send;
+
+    /// Get one page of images along with pagination information
+    fn list_images_page(
+        &self,
+        params: &ListImagesParams,
+    ) -> impl Future<Output = Result<ImagePage, GyazoError>> + Send;
+
+    /// Get the user who owns the access token
+    fn get_user(&self) -> impl Future<Output = Result<UserResponse, GyazoError>> + Send;
+
+    /// Upload an image
+    fn upload_image(
+        &self,
+        param: UploadParams,
+    ) -> impl Future<Output = Result<UploadImageResponse, GyazoError>> + Send;
+
+    /// Delete an image by its ID
+    fn delete_image(
+        &self,
+        image_id: &str,
+    ) -> impl Future<Output = Result<DeleteImageResponse, GyazoError>> + Send;
+
+    /// Download the raw bytes of an image file
+    fn download(&self, url: &str) -> impl Future<Output = Result<Vec<u8>, GyazoError>> + Send;
+
+    /// Get oembed data for an image by ID or URL
+    fn get_oembed(
+        &self,
+        image: &str,
+    ) -> impl Future<Output = Result<OembedResponse, GyazoError>> + Send;
+}
+
+impl GyazoApi for GyazoClient {
+    async fn get_image(&self, image_id: &str) -> Result<GyazoImageResponse, GyazoError> {
+        GyazoClient::get_image(self, image_id).await
+    }
+
+    async fn list_images(&self) -> Result<Vec<GyazoImageResponse>, GyazoError> {
+        GyazoClient::list_images(self).await
+    }
+
+    async fn list_images_page(&self, params: &ListImagesParams) -> Result<ImagePage, GyazoError> {
+        GyazoClient::list_images_page(self, params).await
+    }
+
+    async fn get_user(&self) -> Result<UserResponse, GyazoError> {
+        GyazoClient::get_user(self).await
+    }
+
+    async fn upload_image(&self, param: UploadParams) -> Result<UploadImageResponse, GyazoError> {
+        GyazoClient::upload_image(self, param).await
+    }
+
+    async fn delete_image(&self, image_id: &str) -> Result<DeleteImageResponse, GyazoError> {
+        GyazoClient::delete_image(self, image_id).await
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, GyazoError> {
+        GyazoClient::download(self, url).await
+    }
+
+    async fn get_oembed(&self, image: &str) -> Result<OembedResponse, GyazoError> {
+        GyazoClient::get_oembed(self, image).await
+    }
+}
+
+impl<T: GyazoApi> GyazoApi for &T {
+    fn get_image(
+        &self,
+        image_id: &str,
+    ) -> impl Future<Output = Result<GyazoImageResponse, GyazoError>> + Send {
+        (**self).get_image(image_id)
+    }
+
+    fn list_images(
+        &self,
+    ) -> impl Future<Output = Result<Vec<GyazoImageResponse>, GyazoError>> + Send {
+        (**self).list_images()
+    }
+
+    fn list_images_page(
+        &self,
+        params: &ListImagesParams,
+    ) -> impl Future<Output = Result<ImagePage, GyazoError>> + Send {
+        (**self).list_images_page(params)
+    }
+
+    fn get_user(&self) -> impl Future<Output = Result<UserResponse, GyazoError>> + Send {
+        (**self).get_user()
+    }
+
+    fn upload_image(
+        &self,
+        param: UploadParams,
+    ) -> impl Future<Output = Result<UploadImageResponse, GyazoError>> + Send {
+        (**self).upload_image(param)
+    }
+
+    fn delete_image(
+        &self,
+        image_id: &str,
+    ) -> impl Future<Output = Result<DeleteImageResponse, GyazoError>> + Send {
+        (**self).delete_image(image_id)
+    }
+
+    fn download(&self, url: &str) -> impl Future<Output = Result<Vec<u8>, GyazoError>> + Send {
+        (**self).download(url)
+    }
+
+    fn get_oembed(
+        &self,
+        image: &str,
+    ) -> impl Future<Output = Result<OembedResponse, GyazoError>> + Send {
+        (**self).get_oembed(image)
+    }
+}
@@ -0,0 +1,404 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use gyazo_client::{
+    GyazoClient, GyazoClientOptions, GyazoError, GyazoImageResponse, ListImagesParams, SecretToken,
+    UploadImageResponse, UploadParamsBuilder,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Command-line client for the Gyazo API
+#[derive(Debug, Parser)]
+#[command(name = "gyazo", version, about)]
+struct Cli {
+    /// Gyazo access token
+    #[arg(long, env = "GYAZO_ACCESS_TOKEN", hide_env_values = true, value_parser = parse_token)]
+    token: SecretToken,
+    /// Gyazo Teams name
+    #[arg(long, env = "GYAZO_TEAM")]
+    team: Option<String>,
+    /// Override the API base URL
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Override the upload URL
+    #[arg(long)]
+    upload_url: Option<String>,
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Json,
+    Table,
+    Url,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Upload one or more images
+    Upload {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        metadata: UploadMetadata,
+    },
+    /// Show an image
+    Get { image: String },
+    /// List images
+    List {
+        #[arg(long)]
+        page: Option<u32>,
+        #[arg(long)]
+        per_page: Option<u32>,
+    },
+    /// Delete an image
+    Delete { image: String },
+    /// Show oembed data for an image
+    Oembed { image: String },
+    /// Show the user who owns the access token
+    Whoami,
+}
+
+#[derive(Debug, clap::Args)]
+struct UploadMetadata {
+    #[arg(long)]
+    title: Option<String>,
+    #[arg(long)]
+    desc: Option<String>,
+    #[arg(long)]
+    collection: Option<String>,
+    /// `anyone` or `only_me`
+    #[arg(long)]
+    access_policy: Option<String>,
+    /// Remove EXIF, GPS and other metadata before uploading
+    #[arg(long)]
+    strip_metadata: bool,
+}
+
+/// Result of a command, renderable in every output format
+#[derive(Debug, Default)]
+struct Report {
+    json: Vec<Value>,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+    urls: Vec<String>,
+}
+
+impl Report {
+    fn new(headers: Vec<&'static str>) -> Self {
+        Self {
+            headers,
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, item: &impl Serialize, row: Vec<String>, url: String) {
+        self.json
+            .push(serde_json::to_value(item).unwrap_or(Value::Null));
+        self.rows.push(row);
+        self.urls.push(url);
+    }
+
+    fn render(&self, format: OutputFormat, many: bool) -> String {
+        match format {
+            OutputFormat::Json if many => to_json(&Value::Array(self.json.clone())),
+            OutputFormat::Json => to_json(self.json.first().unwrap_or(&Value::Null)),
+            OutputFormat::Table => render_table(&self.headers, &self.rows),
+            OutputFormat::Url => self.urls.join("\n"),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let client = match GyazoClient::try_new(GyazoClientOptions {
+        access_token: cli.token,
+        base_url: cli.base_url,
+        upload_url: cli.upload_url,
+        team: cli.team,
+    }) {
+        Ok(client) => client,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return ExitCode::from(exit_code(&error));
+        }
+    };
+    let many = matches!(cli.command, Command::Upload { .. } | Command::List { .. });
+    let (report, error) = run(&client, cli.command).await;
+    if let Some(report) = report {
+        let output = report.render(cli.output, many);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+    }
+    match error {
+        Some(error) => ExitCode::from(exit_code(&error)),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+/// Keep the token out of `Debug` output from the moment it is parsed
+fn parse_token(token: &str) -> Result<SecretToken, std::convert::Infallible> {
+    Ok(SecretToken::from(token))
+}
+
+async fn run(client: &GyazoClient, command: Command) -> (Option<Report>, Option<GyazoError>) {
+    match command {
+        Command::Upload { files, metadata } => {
+            let mut report = Report::new(vec!["ID", "PERMALINK", "URL"]);
+            let mut first_error = None;
+            for file in files {
+                match upload(client, &file, &metadata).await {
+                    Ok(uploaded) => {
+                        let row = vec![
+                            uploaded.image_id.clone(),
+                            uploaded.permalink_url.clone(),
+                            uploaded.url.clone(),
+                        ];
+                        let url = uploaded.permalink_url.clone();
+                        report.push(&uploaded, row, url);
+                    }
+                    Err(error) => {
+                        eprintln!("error: {}: {}", file.display(), error);
+                        first_error.get_or_insert(error);
+                    }
+                }
+            }
+            (Some(report), first_error)
+        }
+        Command::Get { image } => {
+            single(async {
+                let image_id = client.parse_image_id(&image)?;
+                let image = client.get_image(image_id.as_str()).await?;
+                let mut report = Report::new(image_headers());
+                let url = image.permalink_url.clone().unwrap_or_default();
+                report.push(&image, image_row(&image), url);
+                Ok(report)
+            })
+            .await
+        }
+        Command::List { page, per_page } => {
+            single(async {
+                let page = client
+                    .list_images_page(&ListImagesParams { page, per_page })
+                    .await?;
+                let mut report = Report::new(image_headers());
+                for image in &page.images {
+                    let url = image.permalink_url.clone().unwrap_or_default();
+                    report.push(image, image_row(image), url);
+                }
+                Ok(report)
+            })
+            .await
+        }
+        Command::Delete { image } => {
+            single(async {
+                let image_id = client.parse_image_id(&image)?;
+                let deleted = client.delete_image(image_id.as_str()).await?;
+                let mut report = Report::new(vec!["ID", "TYPE"]);
+                let row = vec![
+                    deleted.image_id.clone(),
+                    deleted.image_type.as_str().to_string(),
+                ];
+                let url = client.permalink_url(&image_id);
+                report.push(&deleted, row, url);
+                Ok(report)
+            })
+            .await
+        }
+        Command::Oembed { image } => {
+            single(async {
+                let oembed = client.get_oembed(&image).await?;
+                let mut report = Report::new(vec!["URL", "WIDTH", "HEIGHT"]);
+                let row = vec![
+                    oembed.url.clone(),
+                    oembed.width.to_string(),
+                    oembed.height.to_string(),
+                ];
+                let url = oembed.url.clone();
+                report.push(&oembed, row, url);
+                Ok(report)
+            })
+            .await
+        }
+        Command::Whoami => {
+            single(async {
+                let user = client.get_user().await?.user;
+                let mut report = Report::new(vec!["NAME", "UID", "EMAIL"]);
+                let row = vec![
+                    user.name.clone(),
+                    user.uid.clone(),
+                    user.email.clone().unwrap_or_default(),
+                ];
+                let url = user.profile_image.clone().unwrap_or_default();
+                report.push(&user, row, url);
+                Ok(report)
+            })
+            .await
+        }
+    }
+}
+
+async fn single(
+    report: impl std::future::Future<Output = Result<Report, GyazoError>>,
+) -> (Option<Report>, Option<GyazoError>) {
+    match report.await {
+        Ok(report) => (Some(report), None),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            (None, Some(error))
+        }
+    }
+}
+
+async fn upload(
+    client: &GyazoClient,
+    file: &Path,
+    metadata: &UploadMetadata,
+) -> Result<UploadImageResponse, GyazoError> {
+    let imagedata = tokio::fs::read(file)
+        .await
+        .map_err(|e| GyazoError::InvalidInput(format!("cannot read file: {}", e)))?;
+    let mut builder = UploadParamsBuilder::new(imagedata)
+        .app("gyazo-cli")
+        .strip_metadata(metadata.strip_metadata);
+    if let Some(title) = &metadata.title {
+        builder = builder.title(title);
+    }
+    if let Some(desc) = &metadata.desc {
+        builder = builder.desc(desc);
+    }
+    if let Some(collection) = &metadata.collection {
+        builder = builder.collection_id(collection);
+    }
+    if let Some(access_policy) = &metadata.access_policy {
+        builder = builder.access_policy(access_policy)?;
+    }
+    client.upload_image(builder.build()?).await
+}
+
+fn image_headers() -> Vec<&'static str> {
+    vec!["ID", "TYPE", "CREATED AT", "TITLE", "PERMALINK"]
+}
+
+fn image_row(image: &GyazoImageResponse) -> Vec<String> {
+    vec![
+        image.image_id.clone(),
+        image.image_type.as_str().to_string(),
+        image.created_at.clone(),
+        image.metadata.title.clone().unwrap_or_default(),
+        image.permalink_url.clone().unwrap_or_default(),
+    ]
+}
+
+fn to_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![format_row(headers.to_vec())];
+    lines.extend(
+        rows.iter()
+            .map(|row| format_row(row.iter().map(String::as_str).collect())),
+    );
+    lines.join("\n")
+}
+
+/// Process exit code for an error
+///
+/// `2` is used by clap for usage errors.
+fn exit_code(error: &GyazoError) -> u8 {
+    match error {
+        GyazoError::InvalidInput(_)
+        | GyazoError::InvalidUrl(_)
+        | GyazoError::BadRequest
+        | GyazoError::UnprocessableEntity => 3,
+        GyazoError::Unauthorized | GyazoError::Forbidden => 4,
+        GyazoError::NotFound => 5,
+        GyazoError::RateLimitExceeded => 6,
+        GyazoError::InternalServerError | GyazoError::ApiError { .. } => 7,
+        GyazoError::RequestFailed(_) => 8,
+        GyazoError::JsonParseError { .. } | GyazoError::UnexpectedContentType { .. } => 9,
+        GyazoError::Io(_) | GyazoError::Other(_) | GyazoError::NotifyFailed { .. } => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let table = render_table(
+            &["ID", "TITLE"],
+            &[
+                vec!["abc123".to_string(), "first".to_string()],
+                vec!["d".to_string(), String::new()],
+            ],
+        );
+        assert_eq!(table, "ID      TITLE\nabc123  first\nd");
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&GyazoError::NotFound), 5);
+        assert_eq!(exit_code(&GyazoError::Unauthorized), 4);
+        assert_eq!(exit_code(&GyazoError::InvalidInput(String::new())), 3);
+        assert_eq!(exit_code(&GyazoError::RateLimitExceeded), 6);
+        let notify_failed = GyazoError::NotifyFailed {
+            target: "Slack webhook hooks.slack.com".to_string(),
+            status: None,
+            message: String::new(),
+        };
+        assert_eq!(exit_code(&notify_failed), 1);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = Cli::try_parse_from([
+            "gyazo",
+            "--token",
+            "fake_token",
+            "--output",
+            "url",
+            "list",
+            "--page",
+            "2",
+            "--per-page",
+            "10",
+        ])
+        .expect("valid arguments");
+        assert_eq!(cli.output, OutputFormat::Url);
+        assert!(!format!("{:?}", cli).contains("fake_token"));
+        assert!(matches!(
+            cli.command,
+            Command::List {
+                page: Some(2),
+                per_page: Some(10)
+            }
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+//! Response caching for [`GyazoClient::get_image`] and
+//! [`GyazoClient::get_oembed`]
+//!
+//! Enable it with [`GyazoClient::with_cache`]. Responses are reused while
+//! fresh according to their `Cache-Control` header and revalidated with
+//! `If-None-Match` once stale. Entries of an image are dropped when it is
+//! deleted through the client.
+//!
+//! [`GyazoClient::get_image`]: crate::GyazoClient::get_image
+//! [`GyazoClient::get_oembed`]: crate::GyazoClient::get_oembed
+//! [`GyazoClient::with_cache`]: crate::GyazoClient::with_cache
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use reqwest::header::{HeaderMap, CACHE_CONTROL, ETAG};
+use serde::{Deserialize, Serialize};
+
+use crate::dedup::sha256_hex;
+use crate::fs_util::write_atomically;
+
+/// A cached response body
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheEntry {
+    /// JSON response body
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    /// When the response must be revalidated; `None` means it stays fresh for
+    /// as long as the cache keeps it
+    pub fresh_until: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self) -> bool {
+        self.fresh_until
+            .is_none_or(|fresh_until| SystemTime::now() < fresh_until)
+    }
+}
+
+/// Storage for cached responses, grouped by the image they describe
+///
+/// Implementations should treat failures as cache misses rather than errors.
+pub trait Cache: Send + Sync + 'static {
+    fn get(&self, image_id: &str, key: &str) -> Option<CacheEntry>;
+
+    fn insert(&self, image_id: &str, key: &str, entry: CacheEntry);
+
+    /// Remove every entry of an image
+    fn remove_image(&self, image_id: &str);
+}
+
+/// How a response may be cached according to its headers
+pub(crate) enum Freshness {
+    /// `Cache-Control: no-store`
+    NoStore,
+    /// Fresh until the given time; `no-cache` yields the current time
+    Until(SystemTime),
+    /// No explicit lifetime
+    Unspecified,
+}
+
+impl Freshness {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(cache_control) = headers
+            .get(CACHE_CONTROL)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return Freshness::Unspecified;
+        };
+        let mut freshness = Freshness::Unspecified;
+        for directive in cache_control.split(',').map(str::trim) {
+            let directive = directive.to_ascii_lowercase();
+            if directive == "no-store" {
+                return Freshness::NoStore;
+            } else if directive == "no-cache" {
+                freshness = Freshness::Until(SystemTime::now());
+            } else if let Some(max_age) = directive.strip_prefix("max-age=") {
+                if let (Ok(seconds), Freshness::Unspecified) = (max_age.parse(), &freshness) {
+                    freshness = Freshness::Until(SystemTime::now() + Duration::from_secs(seconds));
+                }
+            }
+        }
+        freshness
+    }
+
+    pub(crate) fn fresh_until(&self) -> Option<SystemTime> {
+        match self {
+            Freshness::Until(time) => Some(*time),
+            Freshness::NoStore | Freshness::Unspecified => None,
+        }
+    }
+}
+
+pub(crate) fn etag(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Cache configured on a client
+#[derive(Clone)]
+pub(crate) struct CacheHandle(pub(crate) Arc<dyn Cache>);
+
+impl fmt::Debug for CacheHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CacheHandle")
+    }
+}
+
+/// In-memory [`Cache`] that keeps the most recently used entries
+///
+/// Entries older than `ttl` are dropped even if the server did not limit
+/// their lifetime.
+pub struct MemoryCache {
+    capacity: usize,
+    ttl: Duration,
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    entries: HashMap<(String, String), MemoryEntry>,
+    clock: u64,
+}
+
+struct MemoryEntry {
+    entry: CacheEntry,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Number of entries currently stored, including expired ones
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().entries.is_empty()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, image_id: &str, key: &str) -> Option<CacheEntry> {
+        let mut state = self.state();
+        state.clock += 1;
+        let clock = state.clock;
+        let key = (image_id.to_string(), key.to_string());
+        let stored = state.entries.get_mut(&key)?;
+        if stored.stored_at.elapsed() > self.ttl {
+            state.entries.remove(&key);
+            return None;
+        }
+        stored.last_used = clock;
+        Some(stored.entry.clone())
+    }
+
+    fn insert(&self, image_id: &str, key: &str, entry: CacheEntry) {
+        let mut state = self.state();
+        state.clock += 1;
+        let last_used = state.clock;
+        let key = (image_id.to_string(), key.to_string());
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, stored)| stored.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.entries.insert(
+            key,
+            MemoryEntry {
+                entry,
+                stored_at: Instant::now(),
+                last_used,
+            },
+        );
+    }
+
+    fn remove_image(&self, image_id: &str) {
+        self.state().entries.retain(|(id, _), _| id != image_id);
+    }
+}
+
+/// [`Cache`] that stores one JSON file per response under a directory
+///
+/// Files are grouped in a subdirectory per image so deleting an image removes
+/// all of its entries at once. Entries older than `ttl` are ignored.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl DiskCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    fn image_dir(&self, image_id: &str) -> PathBuf {
+        self.dir.join(sha256_hex(image_id.as_bytes()))
+    }
+
+    /// Path of an entry; the key is hashed since it contains URLs
+    fn path(&self, image_id: &str, key: &str) -> PathBuf {
+        self.image_dir(image_id)
+            .join(format!("{}.json", sha256_hex(key.as_bytes())))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct DiskEntry {
+    key: String,
+    stored_at: SystemTime,
+    entry: CacheEntry,
+}
+
+impl Cache for DiskCache {
+    fn get(&self, image_id: &str, key: &str) -> Option<CacheEntry> {
+        let contents = std::fs::read(self.path(image_id, key)).ok()?;
+        let stored: DiskEntry = serde_json::from_slice(&contents).ok()?;
+        let age = stored.stored_at.elapsed().unwrap_or_default();
+        (stored.key == key && age <= self.ttl).then_some(stored.entry)
+    }
+
+    fn insert(&self, image_id: &str, key: &str, entry: CacheEntry) {
+        let path = self.path(image_id, key);
+        let stored = DiskEntry {
+            key: key.to_string(),
+            stored_at: SystemTime::now(),
+            entry,
+        };
+        let Ok(contents) = serde_json::to_vec(&stored) else {
+            return;
+        };
+        let write = || -> std::io::Result<()> {
+            std::fs::create_dir_all(self.image_dir(image_id))?;
+            write_atomically(&path, &contents)
+        };
+        let _ = write();
+    }
+
+    fn remove_image(&self, image_id: &str) {
+        let _ = std::fs::remove_dir_all(self.image_dir(image_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn entry(body: &str) -> CacheEntry {
+        CacheEntry {
+            body: body.as_bytes().to_vec(),
+            etag: None,
+            fresh_until: None,
+        }
+    }
+
+    #[test]
+    fn test_memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2, Duration::from_secs(60));
+        cache.insert("a", "image", entry("a"));
+        cache.insert("b", "image", entry("b"));
+        assert!(cache.get("a", "image").is_some());
+        cache.insert("c", "image", entry("c"));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b", "image").is_none());
+        assert!(cache.get("a", "image").is_some());
+
+        cache.remove_image("a");
+        assert!(cache.get("a", "image").is_none());
+    }
+
+    #[test]
+    fn test_memory_cache_ttl() {
+        let cache = MemoryCache::new(10, Duration::ZERO);
+        cache.insert("a", "image", entry("a"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(cache.get("a", "image").is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_disk_cache() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cache = DiskCache::new(dir.path(), Duration::from_secs(60));
+        cache.insert("a", "image", entry("a"));
+        cache.insert("a", "oembed", entry("oembed"));
+        cache.insert("b", "image", entry("b"));
+
+        let reopened = DiskCache::new(dir.path(), Duration::from_secs(60));
+        assert_eq!(reopened.get("a", "image"), Some(entry("a")));
+        reopened.remove_image("a");
+        assert!(reopened.get("a", "image").is_none());
+        assert!(reopened.get("a", "oembed").is_none());
+        assert_eq!(reopened.get("b", "image"), Some(entry("b")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_freshness_from_headers() {
+        let freshness = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static(value));
+            Freshness::from_headers(&headers)
+        };
+        assert!(matches!(
+            freshness("no-store, max-age=60"),
+            Freshness::NoStore
+        ));
+        assert!(matches!(
+            Freshness::from_headers(&HeaderMap::new()),
+            Freshness::Unspecified
+        ));
+        let until = freshness("private, max-age=60").fresh_until();
+        assert!(until.is_some_and(|until| until > SystemTime::now()));
+        let until = freshness("max-age=60, no-cache").fresh_until();
+        assert!(until.is_some_and(|until| until <= SystemTime::now()));
+    }
+}
@@ -0,0 +1,436 @@
+//! Deleting many images at once
+//!
+//! [`delete_many`] deletes a list of image IDs concurrently and reports the
+//! outcome of each one. [`delete_matching`] walks the whole image list and
+//! deletes the images selected by an [`ImageFilter`]. Both support a dry run
+//! that only reports what would be deleted.
+
+use std::time::{Duration, SystemTime};
+
+use futures_util::{stream, StreamExt};
+use reqwest::Url;
+
+use crate::date::{format_utc, to_utc};
+use crate::{DeleteImageResponse, GyazoApi, GyazoError, GyazoImageResponse, ListImagesParams};
+
+/// Options for [`delete_many`] and [`delete_matching`]
+#[derive(Clone, Debug)]
+pub struct DeleteOptions {
+    /// Maximum number of requests in flight
+    pub concurrency: usize,
+    /// Only look the images up with `get_image` instead of deleting them
+    pub dry_run: bool,
+    /// Report images that no longer exist as [`DeleteOutcome::AlreadyDeleted`]
+    /// instead of failing with [`GyazoError::NotFound`]
+    pub not_found_is_deleted: bool,
+    /// Images requested per page of the listing in [`delete_matching`]
+    pub per_page: u32,
+}
+
+impl Default for DeleteOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            dry_run: false,
+            not_found_is_deleted: true,
+            per_page: 100,
+        }
+    }
+}
+
+/// What happened to one image
+#[derive(Debug)]
+pub enum DeleteOutcome {
+    Deleted(DeleteImageResponse),
+    /// The image did not exist
+    AlreadyDeleted,
+    /// Dry run: the image exists and would have been deleted
+    WouldDelete(Box<GyazoImageResponse>),
+}
+
+/// Per-image results of [`delete_many`] or [`delete_matching`]
+#[derive(Debug, Default)]
+pub struct DeleteReport {
+    /// Results by image ID, in the order the images were given or listed
+    pub results: Vec<(String, Result<DeleteOutcome, GyazoError>)>,
+}
+
+impl DeleteReport {
+    /// Number of images deleted by this run
+    pub fn deleted(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, result)| matches!(result, Ok(DeleteOutcome::Deleted(_))))
+            .count()
+    }
+
+    /// Number of images that did not exist, in a dry run as well
+    pub fn already_deleted(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|(_, result)| matches!(result, Ok(DeleteOutcome::AlreadyDeleted)))
+            .count()
+    }
+
+    /// Images a dry run would delete
+    pub fn would_delete(&self) -> impl Iterator<Item = &GyazoImageResponse> {
+        self.results.iter().filter_map(|(_, result)| match result {
+            Ok(DeleteOutcome::WouldDelete(image)) => Some(&**image),
+            _ => None,
+        })
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &GyazoError)> {
+        self.results
+            .iter()
+            .filter_map(|(image_id, result)| Some((image_id.as_str(), result.as_ref().err()?)))
+    }
+}
+
+/// Which images [`delete_matching`] deletes
+///
+/// An image must match every condition that is set. A filter with no
+/// conditions matches every image, so [`delete_matching`] refuses it.
+#[derive(Clone, Debug, Default)]
+pub struct ImageFilter {
+    /// App that uploaded the image, compared case-insensitively
+    pub app: Option<String>,
+    /// Latest `created_at`, exclusive, e.g. `2024-01-01`; times with an
+    /// offset are converted to UTC and times without one are taken as UTC
+    ///
+    /// Images whose `created_at` is not such a date never match, and neither
+    /// does anything if this is not one.
+    pub created_before: Option<String>,
+    /// Minimum age of the image, measured against `created_at` in UTC;
+    /// images whose `created_at` is not a date never match
+    pub older_than: Option<Duration>,
+    /// Host of the page the image was captured from, including its
+    /// subdomains, e.g. `example.com`
+    pub source_domain: Option<String>,
+}
+
+impl ImageFilter {
+    /// Whether no condition is set
+    pub fn is_empty(&self) -> bool {
+        let ImageFilter {
+            app,
+            created_before,
+            older_than,
+            source_domain,
+        } = self;
+        app.is_none() && created_before.is_none() && older_than.is_none() && source_domain.is_none()
+    }
+
+    pub fn matches(&self, image: &GyazoImageResponse) -> bool {
+        self.matcher(SystemTime::now())(image)
+    }
+
+    /// Resolve `older_than` once so a whole run uses the same cutoff
+    pub(crate) fn matcher(&self, now: SystemTime) -> impl Fn(&GyazoImageResponse) -> bool + '_ {
+        let cutoffs: Vec<Option<String>> = self
+            .created_before
+            .as_deref()
+            .map(to_utc)
+            .into_iter()
+            .chain(self.older_than.map(|age| {
+                Some(format_utc(
+                    now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH),
+                ))
+            }))
+            .collect();
+        move |image| {
+            let created_at = to_utc(&image.created_at);
+            let metadata = &image.metadata;
+            self.app.as_deref().is_none_or(|app| {
+                metadata
+                    .app
+                    .as_deref()
+                    .is_some_and(|image_app| image_app.eq_ignore_ascii_case(app))
+            }) && self.source_domain.as_deref().is_none_or(|domain| {
+                metadata
+                    .url
+                    .as_deref()
+                    .is_some_and(|url| is_on_domain(url, domain))
+            }) && cutoffs.iter().all(|cutoff| match (&created_at, cutoff) {
+                (Some(created_at), Some(cutoff)) => created_at < cutoff,
+                _ => false,
+            })
+        }
+    }
+}
+
+fn is_on_domain(url: &str, domain: &str) -> bool {
+    let Some(host) = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    else {
+        return false;
+    };
+    let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+    host == domain
+        || host
+            .strip_suffix(&domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Delete every image in `image_ids`
+///
+/// Failures are reported per image and never stop the other deletions.
+pub async fn delete_many<A, I>(api: &A, image_ids: I, options: &DeleteOptions) -> DeleteReport
+where
+    A: GyazoApi + Sync,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let image_ids: Vec<String> = image_ids
+        .into_iter()
+        .map(|image_id| image_id.as_ref().to_string())
+        .collect();
+    let results = stream::iter(image_ids)
+        .map(|image_id| async move {
+            let result = delete_one(api, &image_id, options).await;
+            (image_id, result)
+        })
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+    DeleteReport { results }
+}
+
+pub(crate) async fn delete_one<A: GyazoApi>(
+    api: &A,
+    image_id: &str,
+    options: &DeleteOptions,
+) -> Result<DeleteOutcome, GyazoError> {
+    let result = if options.dry_run {
+        api.get_image(image_id)
+            .await
+            .map(|image| DeleteOutcome::WouldDelete(Box::new(image)))
+    } else {
+        api.delete_image(image_id).await.map(DeleteOutcome::Deleted)
+    };
+    match result {
+        Err(GyazoError::NotFound) if options.not_found_is_deleted => {
+            Ok(DeleteOutcome::AlreadyDeleted)
+        }
+        result => result,
+    }
+}
+
+/// Delete every image in the library that matches `filter`
+///
+/// The whole list is read before anything is deleted, so deletions do not
+/// shift the pages still to be listed. Listing errors abort the cleanup
+/// without deleting anything. A dry run reports the matching images from the
+/// listing without further requests.
+///
+/// An empty filter fails with [`GyazoError::InvalidInput`] instead of
+/// deleting the whole library.
+pub async fn delete_matching<A: GyazoApi + Sync>(
+    api: &A,
+    filter: &ImageFilter,
+    options: &DeleteOptions,
+) -> Result<DeleteReport, GyazoError> {
+    if filter.is_empty() {
+        return Err(GyazoError::InvalidInput(
+            "delete_matching needs at least one filter condition".to_string(),
+        ));
+    }
+    let matches = filter.matcher(SystemTime::now());
+    let mut selected = list_all(api, options.per_page).await?;
+    selected.retain(|image| matches(image));
+
+    if options.dry_run {
+        let results = selected
+            .into_iter()
+            .map(|image| {
+                (
+                    image.image_id.clone(),
+                    Ok(DeleteOutcome::WouldDelete(Box::new(image))),
+                )
+            })
+            .collect();
+        return Ok(DeleteReport { results });
+    }
+    let image_ids = selected.into_iter().map(|image| image.image_id);
+    Ok(delete_many(api, image_ids, options).await)
+}
+
+/// Read every page of the image list
+pub(crate) async fn list_all<A: GyazoApi>(
+    api: &A,
+    per_page: u32,
+) -> Result<Vec<GyazoImageResponse>, GyazoError> {
+    let mut images = Vec::new();
+    let mut page = 1;
+    loop {
+        let listing = api
+            .list_images_page(&ListImagesParams {
+                page: Some(page),
+                per_page: Some(per_page),
+            })
+            .await?;
+        let full_page = listing.images.len() as u32 >= per_page;
+        let has_next_page = match listing.total_count {
+            Some(_) => listing.has_next_page(),
+            None => full_page,
+        };
+        let is_empty = listing.images.is_empty();
+        images.extend(listing.images);
+        if !has_next_page || is_empty {
+            return Ok(images);
+        }
+        page += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::fixtures::upload;
+    use crate::FakeGyazo;
+
+    #[tokio::test]
+    async fn test_delete_many() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let first = upload(&fake, "2024-01-01 00:00:00", |params| params.app("app")).await?;
+        let second = upload(&fake, "2024-01-01 00:00:00", |params| params.app("app")).await?;
+        let missing = "ffffffffffffffffffffffffffffffff";
+
+        let dry_run = DeleteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = delete_many(&fake, [&first, missing], &dry_run).await;
+        assert_eq!(report.would_delete().count(), 1);
+        assert_eq!(report.deleted(), 0);
+        assert_eq!(report.already_deleted(), 1);
+        assert_eq!(fake.len(), 2);
+
+        let report =
+            delete_many(&fake, [&first, missing, &second], &DeleteOptions::default()).await;
+        let ids: Vec<&str> = report.results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, [first.as_str(), missing, second.as_str()]);
+        assert!(matches!(
+            report.results[1].1,
+            Ok(DeleteOutcome::AlreadyDeleted)
+        ));
+        assert_eq!(report.deleted(), 2);
+        assert_eq!(report.already_deleted(), 1);
+        assert!(fake.is_empty());
+
+        let strict = DeleteOptions {
+            not_found_is_deleted: false,
+            ..Default::default()
+        };
+        let report = delete_many(&fake, [&first], &strict).await;
+        assert_eq!(report.deleted(), 0);
+        let failed: Vec<_> = report.failed().collect();
+        assert!(matches!(failed[..], [(id, GyazoError::NotFound)] if id == first));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_matching() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let old = upload(&fake, "2020-05-01T10:00:00+09:00", |params| {
+            params.app("Screenshot")
+        })
+        .await?;
+        let old_other_app =
+            upload(&fake, "2020-05-01 10:00:00", |params| params.app("Chrome")).await?;
+        let now = format_utc(SystemTime::now());
+        let recent = upload(&fake, now, |params| params.app("screenshot")).await?;
+        let filter = ImageFilter {
+            app: Some("screenshot".to_string()),
+            older_than: Some(Duration::from_secs(30 * 86_400)),
+            ..Default::default()
+        };
+        let options = DeleteOptions {
+            per_page: 1,
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let report = delete_matching(&fake, &filter, &options).await?;
+        let would_delete: Vec<&str> = report
+            .would_delete()
+            .map(|image| image.image_id.as_str())
+            .collect();
+        assert_eq!(would_delete, [old.as_str()]);
+        assert_eq!(fake.len(), 3);
+
+        let options = DeleteOptions {
+            dry_run: false,
+            ..options
+        };
+        let report = delete_matching(&fake, &filter, &options).await?;
+        assert_eq!(report.deleted(), 1);
+        assert!(fake.get_image(&old).await.is_err());
+        assert!(fake.get_image(&old_other_app).await.is_ok());
+        assert!(fake.get_image(&recent).await.is_ok());
+
+        let filter = ImageFilter {
+            created_before: Some("2021-01-01".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&fake.get_image(&old_other_app).await?));
+        assert!(!filter.matches(&fake.get_image(&recent).await?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_older_than_converts_offsets_to_utc() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        // 2024-02-29 22:30 and 23:30 UTC
+        let before = upload(&fake, "2024-03-01T07:30:00+09:00", |params| {
+            params.app("app")
+        })
+        .await?;
+        let after = upload(&fake, "2024-03-01T08:30:00+09:00", |params| {
+            params.app("app")
+        })
+        .await?;
+        let filter = ImageFilter {
+            older_than: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        // Cutoff at 2024-02-29 23:00:00 UTC
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_251_200);
+        let matches = filter.matcher(now);
+        assert!(matches(&fake.get_image(&before).await?));
+        assert!(!matches(&fake.get_image(&after).await?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_date_conditions_fail_closed() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let unix_seconds = upload(&fake, "1577836800", |params| params.app("app")).await?;
+        let dated = upload(&fake, "2020-01-01 00:00:00", |params| params.app("app")).await?;
+        let older_than = ImageFilter {
+            older_than: Some(Duration::from_secs(86_400)),
+            ..Default::default()
+        };
+        assert!(!older_than.matches(&fake.get_image(&unix_seconds).await?));
+        assert!(older_than.matches(&fake.get_image(&dated).await?));
+
+        let bad_cutoff = ImageFilter {
+            created_before: Some("next year".to_string()),
+            ..Default::default()
+        };
+        assert!(!bad_cutoff.matches(&fake.get_image(&dated).await?));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_matching_rejects_empty_filter() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        upload(&fake, "2020-01-01 00:00:00", |params| params.app("app")).await?;
+        let result =
+            delete_matching(&fake, &ImageFilter::default(), &DeleteOptions::default()).await;
+        assert!(matches!(result, Err(GyazoError::InvalidInput(_))));
+        assert_eq!(fake.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+//! Helpers for the `created_at` strings of Gyazo API
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Date and time without the time zone, so that `2024-08-10T12:00:00+09:00`
+/// and `2024-08-10 12:00:00` compare equal
+pub(crate) fn normalize_date(date: &str) -> String {
+    date.chars()
+        .take(19)
+        .map(|c| if c == 'T' { ' ' } else { c })
+        .collect()
+}
+
+/// Date and time converted to UTC, so that `2024-08-10T09:00:00+09:00` and
+/// `2024-08-10 00:00:00` compare equal
+///
+/// Accepts `YYYY-MM-DD` and `YYYY-MM-DD[T ]HH:MM:SS[.fraction]` followed by
+/// nothing, `Z` or `±HH[:MM]`. Times without an offset are taken as UTC and
+/// dates without a time as midnight. Anything else, such as an empty string
+/// or Unix seconds, is `None`.
+pub(crate) fn to_utc(date: &str) -> Option<String> {
+    let (seconds, offset) = parse_date(date)?;
+    Some(format_unix(seconds - offset))
+}
+
+/// [`to_utc`], or [`normalize_date`] for values that are not dates, to order
+/// images by `created_at`
+pub(crate) fn sort_key(date: &str) -> String {
+    to_utc(date).unwrap_or_else(|| normalize_date(date))
+}
+
+/// Seconds since the epoch of the local time and the offset in seconds
+fn parse_date(date: &str) -> Option<(i64, i64)> {
+    let number = |range: std::ops::Range<usize>, max: i64| -> Option<i64> {
+        date.get(range)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))?
+            .parse()
+            .ok()
+            .filter(|&n| n <= max)
+    };
+    let bytes = date.as_bytes();
+    if bytes.len() < 10 || bytes[4] != b'-' || bytes[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (number(0..4, 9999)?, number(5..7, 12)?, number(8..10, 31)?);
+    if month == 0 || day == 0 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if bytes.len() == 10 {
+        return Some((days * 86_400, 0));
+    }
+    if bytes.len() < 19
+        || !matches!(bytes[10], b'T' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (hour, minute, second) = (
+        number(11..13, 23)?,
+        number(14..16, 59)?,
+        number(17..19, 60)?,
+    );
+
+    let rest = match date[19..].strip_prefix('.') {
+        Some(fraction) => fraction.trim_start_matches(|c: char| c.is_ascii_digit()),
+        None => &date[19..],
+    };
+    let offset = match rest.as_bytes() {
+        [] | [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), ..] => {
+            let digits: String = rest[1..].chars().filter(|&c| c != ':').collect();
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let (hours, minutes) = match digits.len() {
+                2 => (digits.parse::<i64>().ok()?, 0),
+                4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+                _ => return None,
+            };
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+    Some((days * 86_400 + hour * 3600 + minute * 60 + second, offset))
+}
+
+/// Days since 1970-01-01 of a civil date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Format a time as `YYYY-MM-DD HH:MM:SS` in UTC, comparable with
+/// [`to_utc`]
+pub(crate) fn format_utc(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    format_unix(seconds as i64)
+}
+
+fn format_unix(seconds: i64) -> String {
+    let (days, seconds) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01 00:00:00");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(format_utc(leap_day), "2024-02-29 12:34:56");
+        assert_eq!(
+            normalize_date("2024-02-29T12:34:56+09:00"),
+            normalize_date("2024-02-29 12:34:56")
+        );
+    }
+
+    #[test]
+    fn test_to_utc() {
+        let utc = |date| to_utc(date).unwrap_or_default();
+        assert_eq!(utc("2024-03-01T07:30:00+09:00"), "2024-02-29 22:30:00");
+        assert_eq!(utc("2023-12-31T20:00:00.123-05:00"), "2024-01-01 01:00:00");
+        assert_eq!(utc("2024-08-10T12:00:00+0530"), "2024-08-10 06:30:00");
+        assert_eq!(utc("2024-08-10T12:00:00Z"), "2024-08-10 12:00:00");
+        assert_eq!(utc("2024-08-10 12:00:00"), "2024-08-10 12:00:00");
+        assert_eq!(utc("2024-08-10"), "2024-08-10 00:00:00");
+        assert_eq!(utc("1970-01-01T00:00:00+00:00"), format_utc(UNIX_EPOCH));
+        for date in [
+            "",
+            "1723291200",
+            "2024-13-01",
+            "2024-08-10 12:00",
+            "2024-08-10T12:00:00+9",
+        ] {
+            assert_eq!(to_utc(date), None, "{date}");
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Content-hash deduplication for uploads
+//!
+//! [`Deduplicated`] wraps any [`GyazoApi`] and hashes the bytes of every
+//! upload with SHA-256. If a [`DedupStore`] already knows the hash, the stored
+//! [`UploadImageResponse`] is returned instead of uploading the image again.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use sha2::{Digest, Sha256};
+
+use crate::fs_util::{from_json, to_json_pretty, write_atomically};
+use crate::{
+    DeleteImageResponse, GyazoApi, GyazoError, GyazoImageResponse, ImagePage, ListImagesParams,
+    OembedResponse, UploadImageResponse, UploadParams, UserResponse,
+};
+
+/// Hex-encoded SHA-256 of `data`, the key used by [`DedupStore`]
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Storage for uploads keyed by content hash
+pub trait DedupStore: Send + Sync {
+    /// Look up a previous upload of the content with this hash
+    fn get(&self, sha256: &str) -> Result<Option<UploadImageResponse>, GyazoError>;
+
+    /// Record an upload of the content with this hash
+    fn insert(&self, sha256: &str, uploaded: &UploadImageResponse) -> Result<(), GyazoError>;
+
+    /// Forget every upload of a deleted image
+    fn remove_image(&self, image_id: &str) -> Result<(), GyazoError>;
+}
+
+/// [`DedupStore`] kept in memory for the lifetime of the process
+#[derive(Debug, Default)]
+pub struct MemoryDedupStore {
+    uploads: Mutex<HashMap<String, UploadImageResponse>>,
+}
+
+impl MemoryDedupStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn uploads(&self) -> MutexGuard<'_, HashMap<String, UploadImageResponse>> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl DedupStore for MemoryDedupStore {
+    fn get(&self, sha256: &str) -> Result<Option<UploadImageResponse>, GyazoError> {
+        Ok(self.uploads().get(sha256).cloned())
+    }
+
+    fn insert(&self, sha256: &str, uploaded: &UploadImageResponse) -> Result<(), GyazoError> {
+        self.uploads().insert(sha256.to_string(), uploaded.clone());
+        Ok(())
+    }
+
+    fn remove_image(&self, image_id: &str) -> Result<(), GyazoError> {
+        self.uploads()
+            .retain(|_, uploaded| uploaded.image_id != image_id);
+        Ok(())
+    }
+}
+
+/// [`DedupStore`] persisted as a JSON object of hash to upload response
+///
+/// The whole file is rewritten after every change, which is fine for the
+/// thousands of entries a screenshot library produces.
+#[derive(Debug)]
+pub struct JsonFileDedupStore {
+    path: PathBuf,
+    memory: MemoryDedupStore,
+}
+
+impl JsonFileDedupStore {
+    /// Open the store at `path`, starting empty if the file does not exist
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, GyazoError> {
+        let path = path.into();
+        let uploads = match std::fs::read(&path) {
+            Ok(contents) => from_json(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            memory: MemoryDedupStore {
+                uploads: Mutex::new(uploads),
+            },
+        })
+    }
+
+    fn save(&self, uploads: &HashMap<String, UploadImageResponse>) -> Result<(), GyazoError> {
+        let contents = to_json_pretty(uploads)?;
+        Ok(write_atomically(&self.path, &contents)?)
+    }
+}
+
+impl DedupStore for JsonFileDedupStore {
+    fn get(&self, sha256: &str) -> Result<Option<UploadImageResponse>, GyazoError> {
+        self.memory.get(sha256)
+    }
+
+    fn insert(&self, sha256: &str, uploaded: &UploadImageResponse) -> Result<(), GyazoError> {
+        let mut uploads = self.memory.uploads();
+        uploads.insert(sha256.to_string(), uploaded.clone());
+        self.save(&uploads)
+    }
+
+    fn remove_image(&self, image_id: &str) -> Result<(), GyazoError> {
+        let mut uploads = self.memory.uploads();
+        let len = uploads.len();
+        uploads.retain(|_, uploaded| uploaded.image_id != image_id);
+        if uploads.len() == len {
+            return Ok(());
+        }
+        self.save(&uploads)
+    }
+}
+
+/// [`GyazoApi`] wrapper that skips uploads of already uploaded content
+///
+/// Every other operation is passed through; deleting an image also removes it
+/// from the store so the same bytes can be uploaded again.
+#[derive(Debug)]
+pub struct Deduplicated<A, S> {
+    api: A,
+    store: S,
+}
+
+impl<A: GyazoApi + Sync, S: DedupStore> Deduplicated<A, S> {
+    pub fn new(api: A, store: S) -> Self {
+        Self { api, store }
+    }
+
+    pub fn api(&self) -> &A {
+        &self.api
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Previous upload of these bytes, if any
+    pub fn find(&self, imagedata: &[u8]) -> Result<Option<UploadImageResponse>, GyazoError> {
+        self.store.get(&sha256_hex(imagedata))
+    }
+}
+
+impl<A: GyazoApi + Sync, S: DedupStore> GyazoApi for Deduplicated<A, S> {
+    async fn get_image(&self, image_id: &str) -> Result<GyazoImageResponse, GyazoError> {
+        self.api.get_image(image_id).await
+    }
+
+    async fn list_images(&self) -> Result<Vec<GyazoImageResponse>, GyazoError> {
+        self.api.list_images().await
+    }
+
+    async fn list_images_page(&self, params: &ListImagesParams) -> Result<ImagePage, GyazoError> {
+        self.api.list_images_page(params).await
+    }
+
+    async fn get_user(&self) -> Result<UserResponse, GyazoError> {
+        self.api.get_user().await
+    }
+
+    async fn upload_image(&self, param: UploadParams) -> Result<UploadImageResponse, GyazoError> {
+        let sha256 = sha256_hex(&param.imagedata);
+        if let Some(uploaded) = self.store.get(&sha256)? {
+            return Ok(uploaded);
+        }
+        let uploaded = self.api.upload_image(param).await?;
+        self.store.insert(&sha256, &uploaded)?;
+        Ok(uploaded)
+    }
+
+    async fn delete_image(&self, image_id: &str) -> Result<DeleteImageResponse, GyazoError> {
+        let deleted = self.api.delete_image(image_id).await?;
+        self.store.remove_image(image_id)?;
+        Ok(deleted)
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, GyazoError> {
+        self.api.download(url).await
+    }
+
+    async fn get_oembed(&self, image: &str) -> Result<OembedResponse, GyazoError> {
+        self.api.get_oembed(image).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::png_header;
+    use crate::{FakeGyazo, UploadParamsBuilder};
+
+    fn params(tail: &[u8]) -> anyhow::Result<UploadParams> {
+        Ok(UploadParamsBuilder::new(png_header(1, 1, tail)).build()?)
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deduplicated_upload() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let api = Deduplicated::new(&fake, MemoryDedupStore::new());
+
+        let first = api.upload_image(params(&[1, 2, 3])?).await?;
+        let second = api.upload_image(params(&[1, 2, 3])?).await?;
+        assert_eq!(second.image_id, first.image_id);
+        assert_eq!(fake.len(), 1);
+        assert!(api.find(&png_header(1, 1, &[1, 2, 3]))?.is_some());
+
+        api.upload_image(params(&[4])?).await?;
+        assert_eq!(fake.len(), 2);
+
+        api.delete_image(&first.image_id).await?;
+        assert!(api.find(&png_header(1, 1, &[1, 2, 3]))?.is_none());
+        let third = api.upload_image(params(&[1, 2, 3])?).await?;
+        assert_ne!(third.image_id, first.image_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_json_file_store_persists() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("dedup.json");
+
+        let api = Deduplicated::new(&fake, JsonFileDedupStore::open(&path)?);
+        let first = api.upload_image(params(&[1])?).await?;
+
+        let api = Deduplicated::new(&fake, JsonFileDedupStore::open(&path)?);
+        let second = api.upload_image(params(&[1])?).await?;
+        assert_eq!(second.image_id, first.image_id);
+        assert_eq!(fake.len(), 1);
+        Ok(())
+    }
+}
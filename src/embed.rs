@@ -0,0 +1,346 @@
+//! Snippets that embed an image in Markdown, HTML, Scrapbox and BBCode
+
+use std::fmt::Write;
+
+use crate::image_id::{ImageRef, PUBLIC_HOST};
+use crate::{GyazoImageResponse, OembedResponse, UploadImageResponse};
+
+/// Markup produced by [`Embed::render`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmbedFormat {
+    Markdown,
+    Html,
+    /// Scrapbox/Cosense bracket notation
+    Scrapbox,
+    BBCode,
+}
+
+/// An image linked to its page, ready to be rendered as a snippet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Embed {
+    /// Page the snippet links to, usually the permalink
+    pub link_url: String,
+    /// Image file shown in the snippet
+    pub image_url: String,
+    /// Alternative text
+    pub title: Option<String>,
+    /// Tooltip in Markdown and HTML
+    pub description: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Embed {
+    pub fn new(link_url: impl Into<String>, image_url: impl Into<String>) -> Self {
+        Self {
+            link_url: link_url.into(),
+            image_url: image_url.into(),
+            title: None,
+            description: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Embed an image, falling back to a permalink on `permalink_host` when
+    /// the response has none
+    ///
+    /// Pass [`crate::GyazoClient::permalink_host`] so images of a Gyazo Teams
+    /// client link to the team domain.
+    pub fn from_image(image: &GyazoImageResponse, permalink_host: &str) -> Self {
+        let link_url = image
+            .permalink_url
+            .clone()
+            .unwrap_or_else(|| format!("https://{}/{}", permalink_host, image.image_id));
+        let image_url = image.url.clone().unwrap_or_else(|| {
+            format!(
+                "https://i.gyazo.com/{}.{}",
+                image.image_id,
+                image.image_type.extension()
+            )
+        });
+        Embed {
+            title: image.metadata.title.clone(),
+            description: image.metadata.desc.clone(),
+            ..Embed::new(link_url, image_url)
+        }
+    }
+
+    /// Embed an oEmbed response, linking to the permalink of the image on
+    /// `permalink_host`, or to the image itself when its URL does not identify
+    /// a Gyazo image
+    pub fn from_oembed(oembed: &OembedResponse, permalink_host: &str) -> Self {
+        let link_url = ImageRef::parse(&oembed.url)
+            .map(|image| image.permalink_url(permalink_host))
+            .unwrap_or_else(|_| oembed.url.clone());
+        Embed {
+            title: oembed.title.clone(),
+            width: Some(oembed.width).filter(|&width| width > 0),
+            height: Some(oembed.height).filter(|&height| height > 0),
+            ..Embed::new(link_url, &oembed.url)
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = Some(width);
+        self.height = Some(height);
+        self
+    }
+
+    pub fn render(&self, format: EmbedFormat) -> String {
+        match format {
+            EmbedFormat::Markdown => self.markdown(),
+            EmbedFormat::Html => self.html(),
+            EmbedFormat::Scrapbox => self.scrapbox(),
+            EmbedFormat::BBCode => self.bbcode(),
+        }
+    }
+
+    /// `[![title](image "description")](link)`
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!(
+            "[![{}]({}",
+            escape_markdown(self.title.as_deref().unwrap_or_default()),
+            escape_url(&self.image_url)
+        );
+        if let Some(description) = &self.description {
+            let description = single_line(description)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            let _ = write!(markdown, " \"{}\"", description);
+        }
+        let _ = write!(markdown, ")]({})", escape_url(&self.link_url));
+        markdown
+    }
+
+    /// `<a href="link"><img src="image" alt="title" ...></a>`
+    pub fn html(&self) -> String {
+        let mut html = format!(
+            "<a href=\"{}\"><img src=\"{}\" alt=\"{}\"",
+            escape_html(&self.link_url),
+            escape_html(&self.image_url),
+            escape_html(self.title.as_deref().unwrap_or_default())
+        );
+        if let Some(description) = &self.description {
+            let _ = write!(
+                html,
+                " title=\"{}\"",
+                escape_html(&single_line(description))
+            );
+        }
+        if let Some(width) = self.width {
+            let _ = write!(html, " width=\"{}\"", width);
+        }
+        if let Some(height) = self.height {
+            let _ = write!(html, " height=\"{}\"", height);
+        }
+        html.push_str("></a>");
+        html
+    }
+
+    /// `[image link]`, an image linked to the permalink
+    ///
+    /// The notation has no place for alternative text, so the title is not
+    /// included.
+    pub fn scrapbox(&self) -> String {
+        format!(
+            "[{} {}]",
+            escape_url(&self.image_url),
+            escape_url(&self.link_url)
+        )
+    }
+
+    /// `[url=link][img]image[/img][/url]`
+    pub fn bbcode(&self) -> String {
+        format!(
+            "[url={}][img]{}[/img][/url]",
+            escape_url(&self.link_url),
+            escape_url(&self.image_url)
+        )
+    }
+}
+
+impl From<&UploadImageResponse> for Embed {
+    fn from(image: &UploadImageResponse) -> Self {
+        Embed::new(&image.permalink_url, &image.url)
+    }
+}
+
+impl From<&GyazoImageResponse> for Embed {
+    /// Same as [`Embed::from_image`] on the public Gyazo service
+    fn from(image: &GyazoImageResponse) -> Self {
+        Embed::from_image(image, PUBLIC_HOST)
+    }
+}
+
+impl From<&OembedResponse> for Embed {
+    /// Same as [`Embed::from_oembed`] on the public Gyazo service
+    fn from(oembed: &OembedResponse) -> Self {
+        Embed::from_oembed(oembed, PUBLIC_HOST)
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Backslash-escape characters that would end or format the link text
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in single_line(text).chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '>' | '!') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode characters that delimit URLs in the supported notations
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_whitespace()
+            || c.is_control()
+            || matches!(
+                c,
+                '"' | '\'' | '<' | '>' | '(' | ')' | '[' | ']' | '\\' | '`'
+            )
+        {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                let _ = write!(escaped, "%{:02X}", byte);
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::fixtures;
+    use crate::ImageMetadata;
+
+    fn image() -> GyazoImageResponse {
+        GyazoImageResponse {
+            metadata: ImageMetadata {
+                app: None,
+                title: Some("<b>[Bug] \"quotes\" & *stars*</b>".to_string()),
+                url: None,
+                desc: Some("line one\nline \"two\"".to_string()),
+            },
+            ..fixtures::image()
+        }
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            Embed::from(&image()).markdown(),
+            r#"[![\<b\>\[Bug\] "quotes" & \*stars\*\</b\>](https://i.gyazo.com/abc123.png "line one line \"two\"")](https://gyazo.com/abc123)"#
+        );
+        let embed = Embed::new("https://gyazo.com/abc123", "https://example.com/a (1).png");
+        assert_eq!(
+            embed.markdown(),
+            "[![](https://example.com/a%20%281%29.png)](https://gyazo.com/abc123)"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            Embed::from(&image()).size(640, 480).html(),
+            "<a href=\"https://gyazo.com/abc123\"><img src=\"https://i.gyazo.com/abc123.png\" \
+             alt=\"&lt;b&gt;[Bug] &quot;quotes&quot; &amp; *stars*&lt;/b&gt;\" \
+             title=\"line one line &quot;two&quot;\" width=\"640\" height=\"480\"></a>"
+        );
+    }
+
+    #[test]
+    fn test_from_image_on_team_host() {
+        let image = GyazoImageResponse {
+            permalink_url: None,
+            ..image()
+        };
+        assert_eq!(
+            Embed::from_image(&image, "myteam.gyazo.com").link_url,
+            "https://myteam.gyazo.com/abc123"
+        );
+        assert_eq!(Embed::from(&image).link_url, "https://gyazo.com/abc123");
+    }
+
+    #[test]
+    fn test_scrapbox_and_bbcode() {
+        let embed = Embed::from(&image());
+        assert_eq!(
+            embed.render(EmbedFormat::Scrapbox),
+            "[https://i.gyazo.com/abc123.png https://gyazo.com/abc123]"
+        );
+        assert_eq!(
+            embed.render(EmbedFormat::BBCode),
+            "[url=https://gyazo.com/abc123][img]https://i.gyazo.com/abc123.png[/img][/url]"
+        );
+        let embed = Embed::new("https://example.com/[x]", "https://example.com/x.png");
+        assert_eq!(
+            embed.bbcode(),
+            "[url=https://example.com/%5Bx%5D][img]https://example.com/x.png[/img][/url]"
+        );
+    }
+
+    #[test]
+    fn test_from_oembed() {
+        let oembed = OembedResponse {
+            version: "1.0".to_string(),
+            image_type: "photo".to_string(),
+            provider_name: "Gyazo".to_string(),
+            provider_url: "https://gyazo.com".to_string(),
+            url: "https://i.gyazo.com/abc123.png".to_string(),
+            width: 640,
+            height: 0,
+            html: None,
+            title: Some("Screenshot".to_string()),
+            thumbnail_url: None,
+            thumbnail_width: None,
+            thumbnail_height: None,
+        };
+        let embed = Embed::from(&oembed);
+        assert_eq!(embed.link_url, "https://gyazo.com/abc123");
+        let team_embed = Embed::from_oembed(&oembed, "myteam.gyazo.com");
+        assert_eq!(team_embed.link_url, "https://myteam.gyazo.com/abc123");
+        assert_eq!(embed.width, Some(640));
+        assert_eq!(embed.height, None);
+        assert_eq!(
+            embed.html(),
+            "<a href=\"https://gyazo.com/abc123\"><img src=\"https://i.gyazo.com/abc123.png\" \
+             alt=\"Screenshot\" width=\"640\"></a>"
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Backup of a whole Gyazo library to a local directory
+//!
+//! [`export`] walks every page of the image list, downloads the original files
+//! and writes a manifest of image metadata next to them. Running it again only
+//! downloads images that are missing or whose file size does not match the
+//! manifest.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::fs_util::{from_json, from_json_lines, to_json, to_json_pretty, write_atomically_async};
+use crate::image_id::is_valid_id;
+use crate::{GyazoApi, GyazoError, GyazoImageResponse, ListImagesParams};
+
+/// Format of the manifest written by [`export`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// `manifest.json`, a single JSON array
+    #[default]
+    Json,
+    /// `manifest.ndjson`, one JSON object per line
+    Ndjson,
+}
+
+impl ManifestFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            ManifestFormat::Json => "manifest.json",
+            ManifestFormat::Ndjson => "manifest.ndjson",
+        }
+    }
+}
+
+/// Options for [`export`]
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// Maximum number of downloads in flight
+    pub concurrency: usize,
+    /// Images requested per page of the listing
+    pub per_page: u32,
+    pub manifest_format: ManifestFormat,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            per_page: 100,
+            manifest_format: ManifestFormat::default(),
+        }
+    }
+}
+
+/// One exported image in the manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    /// File name relative to the export directory
+    pub file_name: String,
+    /// Size of the downloaded file in bytes
+    pub size: u64,
+    pub image: GyazoImageResponse,
+}
+
+/// Summary of an [`export`] run
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    /// Number of images downloaded in this run
+    pub downloaded: usize,
+    /// Number of images skipped because they were already exported
+    pub skipped: usize,
+    /// Images that could not be exported, by image ID
+    pub failed: Vec<(String, GyazoError)>,
+}
+
+enum Outcome {
+    Downloaded(ManifestEntry),
+    Skipped(ManifestEntry),
+}
+
+/// Export every image of the library into `dir`
+///
+/// Listing errors abort the export; download errors are collected in
+/// [`ExportReport::failed`] so one broken image does not stop the backup.
+pub async fn export<A: GyazoApi + Sync>(
+    api: &A,
+    dir: impl AsRef<Path>,
+    options: &ExportOptions,
+) -> Result<ExportReport, GyazoError> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).await?;
+    let manifest_path = dir.join(options.manifest_format.file_name());
+    let mut manifest = Manifest::load(&manifest_path, options.manifest_format).await?;
+    let mut report = ExportReport::default();
+
+    let mut page = 1;
+    loop {
+        let listing = api
+            .list_images_page(&ListImagesParams {
+                page: Some(page),
+                per_page: Some(options.per_page),
+            })
+            .await?;
+        let outcomes: Vec<_> = stream::iter(&listing.images)
+            .map(|image| async {
+                let previous = manifest.get(&image.image_id);
+                let outcome = export_image(api, dir, image, previous).await;
+                (image.image_id.clone(), outcome)
+            })
+            .buffer_unordered(options.concurrency.max(1))
+            .collect()
+            .await;
+        for (image_id, outcome) in outcomes {
+            match outcome {
+                Ok(Outcome::Downloaded(entry)) => {
+                    report.downloaded += 1;
+                    manifest.insert(entry);
+                }
+                Ok(Outcome::Skipped(entry)) => {
+                    report.skipped += 1;
+                    manifest.insert(entry);
+                }
+                Err(error) => report.failed.push((image_id, error)),
+            }
+        }
+        // Persist after every page so an interrupted export can resume
+        manifest
+            .save(&manifest_path, options.manifest_format)
+            .await?;
+
+        let full_page = listing.images.len() as u32 >= options.per_page;
+        let has_next_page = match listing.total_count {
+            Some(_) => listing.has_next_page(),
+            None => full_page,
+        };
+        if !has_next_page || listing.images.is_empty() {
+            break;
+        }
+        page += 1;
+    }
+    Ok(report)
+}
+
+async fn export_image<A: GyazoApi>(
+    api: &A,
+    dir: &Path,
+    image: &GyazoImageResponse,
+    previous: Option<&ManifestEntry>,
+) -> Result<Outcome, GyazoError> {
+    // The ID becomes the file name, so it must not name a path
+    if !is_valid_id(&image.image_id) {
+        return Err(GyazoError::InvalidInput(format!(
+            "image ID {:?} is not a Gyazo image ID",
+            image.image_id
+        )));
+    }
+    if let Some(previous) = previous {
+        let path = dir.join(&previous.file_name);
+        if let Ok(metadata) = fs::metadata(&path).await {
+            if metadata.len() == previous.size {
+                return Ok(Outcome::Skipped(ManifestEntry {
+                    image: image.clone(),
+                    ..previous.clone()
+                }));
+            }
+        }
+    }
+    let url = image
+        .url
+        .as_deref()
+        .ok_or_else(|| GyazoError::InvalidInput("image has no url".to_string()))?;
+    let bytes = api.download(url).await?;
+    let file_name = file_name(image);
+    write_atomically_async(&dir.join(&file_name), &bytes).await?;
+    Ok(Outcome::Downloaded(ManifestEntry {
+        file_name,
+        size: bytes.len() as u64,
+        image: image.clone(),
+    }))
+}
+
+fn file_name(image: &GyazoImageResponse) -> String {
+    format!("{}.{}", image.image_id, image.image_type.extension())
+}
+
+/// Manifest entries in export order, indexed by image ID
+#[derive(Default)]
+struct Manifest {
+    entries: Vec<ManifestEntry>,
+    index: HashMap<String, usize>,
+}
+
+impl Manifest {
+    async fn load(path: &Path, format: ManifestFormat) -> Result<Self, GyazoError> {
+        let contents = match fs::read(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let entries: Vec<ManifestEntry> = match format {
+            ManifestFormat::Json => from_json(&contents)?,
+            ManifestFormat::Ndjson => from_json_lines(&contents)?,
+        };
+        let mut manifest = Self::default();
+        entries.into_iter().for_each(|entry| manifest.insert(entry));
+        Ok(manifest)
+    }
+
+    fn get(&self, image_id: &str) -> Option<&ManifestEntry> {
+        self.index.get(image_id).map(|&i| &self.entries[i])
+    }
+
+    fn insert(&mut self, entry: ManifestEntry) {
+        match self.index.get(&entry.image.image_id) {
+            Some(&i) => self.entries[i] = entry,
+            None => {
+                self.index
+                    .insert(entry.image.image_id.clone(), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    async fn save(&self, path: &Path, format: ManifestFormat) -> Result<(), GyazoError> {
+        let contents = match format {
+            ManifestFormat::Json => to_json_pretty(&self.entries)?,
+            ManifestFormat::Ndjson => {
+                let mut contents = Vec::new();
+                for entry in &self.entries {
+                    contents.extend(to_json(entry)?);
+                    contents.push(b'\n');
+                }
+                contents
+            }
+        };
+        Ok(write_atomically_async(path, &contents).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::png_header;
+    use crate::{FakeGyazo, UploadParamsBuilder};
+
+    async fn fake_with_images(count: u8) -> anyhow::Result<FakeGyazo> {
+        let fake = FakeGyazo::new();
+        for i in 0..count {
+            let params = UploadParamsBuilder::new(png_header(1, 1, &vec![i; usize::from(i) + 1]))
+                .title(format!("image {}", i))
+                .build()?;
+            fake.upload_image(params).await?;
+        }
+        Ok(fake)
+    }
+
+    #[tokio::test]
+    async fn test_export_and_resume() -> anyhow::Result<()> {
+        let fake = fake_with_images(3).await?;
+        let dir = tempfile::tempdir()?;
+        let options = ExportOptions {
+            per_page: 2,
+            ..Default::default()
+        };
+
+        let report = export(&fake, dir.path(), &options).await?;
+        assert_eq!(report.downloaded, 3);
+        assert!(report.failed.is_empty());
+
+        let manifest: Vec<ManifestEntry> =
+            serde_json::from_slice(&std::fs::read(dir.path().join("manifest.json"))?)?;
+        assert_eq!(manifest.len(), 3);
+        let first = manifest
+            .iter()
+            .find(|entry| entry.image.metadata.title.as_deref() == Some("image 0"))
+            .expect("first image is in the manifest");
+        let first_data = png_header(1, 1, &[0]);
+        assert_eq!(first.size, first_data.len() as u64);
+        assert_eq!(
+            std::fs::read(dir.path().join(&first.file_name))?,
+            first_data
+        );
+
+        let report = export(&fake, dir.path(), &options).await?;
+        assert_eq!(report.downloaded, 0);
+        assert_eq!(report.skipped, 3);
+
+        std::fs::write(dir.path().join(&first.file_name), [9, 9, 9])?;
+        let report = export(&fake, dir.path(), &options).await?;
+        assert_eq!(report.downloaded, 1);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(
+            std::fs::read(dir.path().join(&first.file_name))?,
+            first_data
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_ndjson() -> anyhow::Result<()> {
+        let fake = fake_with_images(2).await?;
+        let dir = tempfile::tempdir()?;
+        let options = ExportOptions {
+            manifest_format: ManifestFormat::Ndjson,
+            ..Default::default()
+        };
+
+        export(&fake, dir.path(), &options).await?;
+        let manifest = std::fs::read_to_string(dir.path().join("manifest.ndjson"))?;
+        assert_eq!(manifest.lines().count(), 2);
+
+        let report = export(&fake, dir.path(), &options).await?;
+        assert_eq!(report.skipped, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_rejects_unsafe_image_ids() -> anyhow::Result<()> {
+        let fake = fake_with_images(1).await?;
+        let mut image = fake.list_images().await?.remove(0);
+        image.image_id = "../escaped".to_string();
+        fake.insert_image(image);
+        let parent = tempfile::tempdir()?;
+        let dir = parent.path().join("export");
+        std::fs::create_dir(&dir)?;
+
+        let report = export(&fake, &dir, &ExportOptions::default()).await?;
+        assert_eq!(report.downloaded, 1);
+        assert!(matches!(
+            report.failed[..],
+            [(ref id, GyazoError::InvalidInput(_))] if id == "../escaped"
+        ));
+        assert!(!parent.path().join("escaped.png").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{
+    DeleteImageResponse, GyazoApi, GyazoError, GyazoImageResponse, ImageFormat, ImageId,
+    ImageMetadata, ImagePage, ListImagesParams, OembedResponse, UploadImageResponse, UploadParams,
+    User, UserResponse,
+};
+
+const DEFAULT_CREATED_AT: &str = "2024-01-01 00:00:00";
+const DEFAULT_PER_PAGE: u32 = 20;
+const MAX_PER_PAGE: u32 = 100;
+
+/// In-memory implementation of [`GyazoApi`] for deterministic tests
+///
+/// Uploaded images are stored in memory and assigned sequential IDs
+/// (`00000000000000000000000000000001`, `...02`, ...). Listing returns the
+/// newest image first, like the real API, and image URLs returned by uploads
+/// can be passed to [`GyazoApi::download`].
+#[derive(Debug, Default)]
+pub struct FakeGyazo {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    next_id: u64,
+    images: Vec<FakeImage>,
+}
+
+#[derive(Debug)]
+struct FakeImage {
+    image: GyazoImageResponse,
+    imagedata: Vec<u8>,
+}
+
+impl FakeGyazo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed an image record, e.g. to test code that reads existing images
+    pub fn insert_image(&self, image: GyazoImageResponse) {
+        self.state().images.push(FakeImage {
+            image,
+            imagedata: Vec::new(),
+        });
+    }
+
+    /// Raw bytes of an uploaded image
+    pub fn image_data(&self, image_id: &str) -> Option<Vec<u8>> {
+        self.state()
+            .images
+            .iter()
+            .find(|stored| stored.image.image_id == image_id)
+            .map(|stored| stored.imagedata.clone())
+    }
+
+    /// Number of images currently stored
+    pub fn len(&self) -> usize {
+        self.state().images.len()
+    }
+
+    /// Whether no images are stored
+    pub fn is_empty(&self) -> bool {
+        self.state().images.is_empty()
+    }
+
+    fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl GyazoApi for FakeGyazo {
+    async fn get_image(&self, image_id: &str) -> Result<GyazoImageResponse, GyazoError> {
+        let state = self.state();
+        let stored = state
+            .images
+            .iter()
+            .find(|stored| stored.image.image_id == image_id)
+            .ok_or(GyazoError::NotFound)?;
+        Ok(stored.image.clone())
+    }
+
+    async fn list_images(&self) -> Result<Vec<GyazoImageResponse>, GyazoError> {
+        let state = self.state();
+        Ok(state
+            .images
+            .iter()
+            .rev()
+            .map(|stored| stored.image.clone())
+            .collect())
+    }
+
+    async fn list_images_page(&self, params: &ListImagesParams) -> Result<ImagePage, GyazoError> {
+        let page = params.page.unwrap_or(1).max(1);
+        let per_page = params
+            .per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE);
+        // Pages past the end, including ones too far to address, are empty
+        let offset = (page - 1)
+            .checked_mul(per_page)
+            .map_or(usize::MAX, |offset| offset as usize);
+        let state = self.state();
+        let images = state
+            .images
+            .iter()
+            .rev()
+            .skip(offset)
+            .take(per_page as usize)
+            .map(|stored| stored.image.clone())
+            .collect();
+        Ok(ImagePage {
+            images,
+            total_count: Some(state.images.len() as u64),
+            current_page: Some(page),
+            per_page: Some(per_page),
+        })
+    }
+
+    async fn get_user(&self) -> Result<UserResponse, GyazoError> {
+        Ok(UserResponse {
+            user: User {
+                email: None,
+                name: "fake".to_string(),
+                profile_image: None,
+                uid: "fake".to_string(),
+            },
+        })
+    }
+
+    async fn upload_image(&self, param: UploadParams) -> Result<UploadImageResponse, GyazoError> {
+        let mut state = self.state();
+        state.next_id += 1;
+        let image_id = format!("{:032x}", state.next_id);
+        let permalink_url = format!("https://gyazo.com/{}", image_id);
+        let thumb_url = format!("https://thumb.gyazo.com/thumb/{}", image_id);
+        let image_type = ImageFormat::sniff(&param.imagedata).unwrap_or(ImageFormat::Png);
+        let url = format!(
+            "https://i.gyazo.com/{}.{}",
+            image_id,
+            image_type.extension()
+        );
+        let image = GyazoImageResponse {
+            image_id: image_id.clone(),
+            permalink_url: Some(permalink_url.clone()),
+            thumb_url: Some(thumb_url.clone()),
+            url: Some(url.clone()),
+            image_type: image_type.clone(),
+            created_at: param
+                .created_at
+                .unwrap_or_else(|| DEFAULT_CREATED_AT.to_string()),
+            metadata: ImageMetadata {
+                app: param.app,
+                title: param.title,
+                url: param.referer_url,
+                desc: param.desc,
+            },
+            ocr: None,
+        };
+        state.images.push(FakeImage {
+            image,
+            imagedata: param.imagedata,
+        });
+        Ok(UploadImageResponse {
+            image_id,
+            permalink_url,
+            thumb_url,
+            url,
+            image_type,
+        })
+    }
+
+    async fn delete_image(&self, image_id: &str) -> Result<DeleteImageResponse, GyazoError> {
+        let mut state = self.state();
+        let index = state
+            .images
+            .iter()
+            .position(|stored| stored.image.image_id == image_id)
+            .ok_or(GyazoError::NotFound)?;
+        let stored = state.images.remove(index);
+        Ok(DeleteImageResponse {
+            image_id: stored.image.image_id,
+            image_type: stored.image.image_type,
+        })
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>, GyazoError> {
+        let image_id = ImageId::parse(url)?;
+        self.image_data(image_id.as_str())
+            .ok_or(GyazoError::NotFound)
+    }
+
+    async fn get_oembed(&self, image: &str) -> Result<OembedResponse, GyazoError> {
+        let image_id = ImageId::parse(image)?;
+        let state = self.state();
+        let stored = state
+            .images
+            .iter()
+            .find(|stored| stored.image.image_id == image_id.as_str())
+            .ok_or(GyazoError::NotFound)?;
+        Ok(OembedResponse {
+            version: "1.0".to_string(),
+            image_type: "photo".to_string(),
+            provider_name: "Gyazo".to_string(),
+            provider_url: "https://gyazo.com".to_string(),
+            url: format!(
+                "https://i.gyazo.com/{}.{}",
+                stored.image.image_id,
+                stored.image.image_type.extension()
+            ),
+            width: 0,
+            height: 0,
+            html: None,
+            title: stored.image.metadata.title.clone(),
+            thumbnail_url: stored.image.thumb_url.clone(),
+            thumbnail_width: None,
+            thumbnail_height: None,
+        })
+    }
+}
+
+/// Test data shared by the unit tests of several modules
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::image_format::png_header;
+    use crate::{
+        GyazoApi, GyazoError, GyazoImageResponse, ImageFormat, ImageMetadata, UploadParamsBuilder,
+    };
+
+    /// Upload a 1x1 PNG created at `created_at` with the metadata set by
+    /// `params` and return its ID
+    pub(crate) async fn upload<A: GyazoApi>(
+        api: &A,
+        created_at: impl Into<String>,
+        params: impl FnOnce(UploadParamsBuilder) -> UploadParamsBuilder,
+    ) -> Result<String, GyazoError> {
+        let builder = UploadParamsBuilder::new(png_header(1, 1, &[])).created_at(created_at);
+        let params = params(builder).build()?;
+        Ok(api.upload_image(params).await?.image_id)
+    }
+
+    /// Image `abc123` on gyazo.com without metadata or OCR text
+    pub(crate) fn image() -> GyazoImageResponse {
+        GyazoImageResponse {
+            image_id: "abc123".to_string(),
+            permalink_url: Some("https://gyazo.com/abc123".to_string()),
+            thumb_url: None,
+            url: Some("https://i.gyazo.com/abc123.png".to_string()),
+            image_type: ImageFormat::Png,
+            created_at: "2024-08-10 12:00:00".to_string(),
+            metadata: ImageMetadata {
+                app: None,
+                title: None,
+                url: None,
+                desc: None,
+            },
+            ocr: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::upload;
+    use super::*;
+    use crate::image_format::png_header;
+
+    #[tokio::test]
+    async fn test_upload_get_list_delete() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let first = upload(&fake, DEFAULT_CREATED_AT, |params| params.title("first")).await?;
+        let second = upload(&fake, DEFAULT_CREATED_AT, |params| params.title("second")).await?;
+        assert_eq!(first, "00000000000000000000000000000001");
+        assert_eq!(second, "00000000000000000000000000000002");
+
+        let image = fake.get_image(&first).await?;
+        assert_eq!(image.metadata.title, Some("first".to_string()));
+        assert_eq!(fake.image_data(&first), Some(png_header(1, 1, &[])));
+        let url = image.url.expect("uploaded images have a URL");
+        assert_eq!(fake.download(&url).await?, png_header(1, 1, &[]));
+
+        let images = fake.list_images().await?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].image_id, second);
+
+        let page = fake
+            .list_images_page(&ListImagesParams {
+                page: Some(2),
+                per_page: Some(1),
+            })
+            .await?;
+        assert_eq!(page.images[0].image_id, first);
+        assert_eq!(page.total_count, Some(2));
+        assert!(!page.has_next_page());
+        let page = fake
+            .list_images_page(&ListImagesParams {
+                page: Some(u32::MAX),
+                per_page: Some(100),
+            })
+            .await?;
+        assert!(page.images.is_empty());
+
+        let deleted = fake.delete_image(&first).await?;
+        assert_eq!(deleted.image_id, first);
+        assert_eq!(deleted.image_type, ImageFormat::Png);
+        assert_eq!(fake.len(), 1);
+        assert!(matches!(
+            fake.get_image(&first).await,
+            Err(GyazoError::NotFound)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_oembed() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let image_id = upload(&fake, DEFAULT_CREATED_AT, |params| params.title("oembed")).await?;
+        let oembed = fake
+            .get_oembed(&format!("https://gyazo.com/{}", image_id))
+            .await?;
+        assert_eq!(oembed.url, format!("https://i.gyazo.com/{}.png", image_id));
+        assert!(fake.get_oembed("https://example.com/abc").await.is_err());
+        Ok(())
+    }
+}
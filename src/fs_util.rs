@@ -0,0 +1,91 @@
+//! Helpers for the files kept by export, sync, deduplication, the local index,
+//! the disk cache and the watcher
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::gyazo_client::body_snippet;
+use crate::GyazoError;
+
+/// Temporary file next to `path` that is renamed over it once written
+fn tmp_path(path: &Path) -> OsString {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp
+}
+
+/// Replace the file at `path` with `contents`, so readers and a crash midway
+/// never leave a partially written file behind
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Async version of [`write_atomically`]
+pub(crate) async fn write_atomically_async(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+/// Parse a JSON file, reporting malformed contents like a malformed response
+pub(crate) fn from_json<T: DeserializeOwned>(contents: &[u8]) -> Result<T, GyazoError> {
+    serde_json::from_slice(contents).map_err(|source| json_error(source, contents))
+}
+
+/// Parse one JSON value per line, skipping blank lines
+pub(crate) fn from_json_lines<T: DeserializeOwned>(contents: &[u8]) -> Result<Vec<T>, GyazoError> {
+    contents
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(from_json)
+        .collect()
+}
+
+pub(crate) fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, GyazoError> {
+    serde_json::to_vec(value).map_err(|source| json_error(source, &[]))
+}
+
+pub(crate) fn to_json_pretty<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, GyazoError> {
+    serde_json::to_vec_pretty(value).map_err(|source| json_error(source, &[]))
+}
+
+fn json_error(source: serde_json::Error, contents: &[u8]) -> GyazoError {
+    GyazoError::JsonParseError {
+        source,
+        snippet: body_snippet(contents),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_atomically() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("state.json");
+        write_atomically(&path, b"first")?;
+        write_atomically_async(&path, b"second").await?;
+        assert_eq!(std::fs::read(&path)?, b"second");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_errors_keep_a_snippet() -> anyhow::Result<()> {
+        let values: Vec<u32> = from_json_lines(b"1\n\n2\n")?;
+        assert_eq!(values, [1, 2]);
+        let result = from_json_lines::<u32>(b"1\n{oops\n");
+        assert!(matches!(
+            result,
+            Err(GyazoError::JsonParseError { ref snippet, .. }) if snippet == "{oops"
+        ));
+        Ok(())
+    }
+}
@@ -1,18 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use futures_util::Stream;
+use reqwest::header::{HeaderMap, IF_NONE_MATCH};
 use reqwest::multipart::Form;
 use reqwest::{Client, StatusCode, Url};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::cache::{self, Cache, CacheEntry, CacheHandle, Freshness};
+use crate::cleanup::{self, DeleteOptions, DeleteReport};
+use crate::image_id::ImageRef;
+use crate::image_id::PUBLIC_HOST;
+use crate::middleware::MiddlewareStack;
+use crate::watch::{self, WatchOptions};
+use crate::{ImageFormat, ImageId, Middleware, SecretToken};
+
 const DEFAULT_BASE_URL: &str = "https://api.gyazo.com";
 const DEFAULT_UPLOAD_URL: &str = "https://upload.gyazo.com";
+/// Largest image accepted by default, matching Gyazo's upload limit
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+/// Largest width and height accepted by default
+const DEFAULT_MAX_DIMENSION: u32 = 16_384;
 
 /// Error types for the Gyazo API client
 #[derive(Error, Debug)]
 pub enum GyazoError {
     #[error("HTTP request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
-    #[error("Failed to parse JSON: {0}")]
-    JsonParseError(#[from] serde_json::Error),
+    #[error("Failed to parse JSON: {source}, body: {snippet}")]
+    JsonParseError {
+        source: serde_json::Error,
+        snippet: String,
+    },
+    #[error("Unexpected content type: {content_type}, body: {snippet}")]
+    UnexpectedContentType {
+        content_type: String,
+        snippet: String,
+    },
     #[error("Bad Request: Invalid request parameters")]
     BadRequest,
     #[error("Unauthorized: Authentication required")]
@@ -35,99 +61,396 @@ pub enum GyazoError {
     InvalidInput(String),
     #[error("Invalid url: {0}")]
     InvalidUrl(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A webhook of a notifier rejected a message or could not be reached
+    #[error("Notification to {target} failed: {message}")]
+    NotifyFailed {
+        /// Kind and host of the target; the rest of the URL is left out
+        target: String,
+        /// Status of the response, `None` when no response was received
+        status: Option<StatusCode>,
+        message: String,
+    },
 }
 
 /// Gyazo API client
 #[derive(Clone, Debug)]
 pub struct GyazoClient {
     client: Client,
-    access_token: String,
+    access_token: SecretToken,
     base_url: Url,
     upload_url: Url,
+    permalink_host: String,
+    middleware: MiddlewareStack,
+    cache: Option<CacheHandle>,
 }
 
 #[derive(Default, Clone, Debug)]
 pub struct GyazoClientOptions {
-    pub access_token: String,
+    pub access_token: SecretToken,
     pub base_url: Option<String>,
     pub upload_url: Option<String>,
+    /// Gyazo Teams name, e.g. `"myteam"` for `https://myteam.gyazo.com`
+    ///
+    /// API, upload and permalink hosts are derived from the team name.
+    /// `base_url` and `upload_url` still take precedence when set.
+    pub team: Option<String>,
 }
 
 impl GyazoClient {
     /// Create a new GyazoClient instance
+    ///
+    /// Panics when the team name or a URL is invalid; use
+    /// [`try_new`](Self::try_new) for options that come from user input.
     pub fn new(options: GyazoClientOptions) -> Self {
-        let base_url = options
-            .base_url
-            .map(|url| Url::parse(&url).expect("base_url must be a valid URL"))
-            .unwrap_or_else(|| Url::parse(DEFAULT_BASE_URL).expect("base_url must be a valid URL"));
-        let upload_url = options
-            .upload_url
-            .map(|url| Url::parse(&url).expect("upload_url must be a valid URL"))
-            .unwrap_or_else(|| {
-                Url::parse(DEFAULT_UPLOAD_URL).expect("upload_url must be a valid URL")
-            });
-        GyazoClient {
+        Self::try_new(options).unwrap_or_else(|e| panic!("invalid GyazoClient options: {}", e))
+    }
+
+    /// Create a new GyazoClient instance, failing with
+    /// [`GyazoError::InvalidInput`] for an invalid team name and
+    /// [`GyazoError::InvalidUrl`] for an invalid base or upload URL
+    pub fn try_new(options: GyazoClientOptions) -> Result<Self, GyazoError> {
+        if let Some(team) = options.team.as_deref() {
+            if team.is_empty() || !team.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(GyazoError::InvalidInput(format!(
+                    "not a valid Gyazo Teams name: {}",
+                    team
+                )));
+            }
+        }
+        let team_url = options
+            .team
+            .as_deref()
+            .map(|team| format!("https://{}.{}", team, PUBLIC_HOST));
+        let parse = |name: &str, url: &str| {
+            Url::parse(url).map_err(|e| GyazoError::InvalidUrl(format!("{} {}: {}", name, url, e)))
+        };
+        let base_url = parse(
+            "base_url",
+            options
+                .base_url
+                .as_deref()
+                .or(team_url.as_deref())
+                .unwrap_or(DEFAULT_BASE_URL),
+        )?;
+        let upload_url = parse(
+            "upload_url",
+            options
+                .upload_url
+                .as_deref()
+                .or(team_url.as_deref())
+                .unwrap_or(DEFAULT_UPLOAD_URL),
+        )?;
+        let permalink_host = match options.team {
+            Some(team) => format!("{}.{}", team, PUBLIC_HOST),
+            None => PUBLIC_HOST.to_string(),
+        };
+        Ok(GyazoClient {
             client: Client::new(),
             access_token: options.access_token,
             base_url,
             upload_url,
+            permalink_host,
+            middleware: MiddlewareStack::default(),
+            cache: None,
+        })
+    }
+
+    /// Add a middleware that runs around every request
+    ///
+    /// Middleware runs in the order it was added.
+    pub fn with_middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Cache responses of [`get_image`](Self::get_image) and
+    /// [`get_oembed`](Self::get_oembed)
+    ///
+    /// See [`crate::cache`] for how entries are refreshed and invalidated.
+    pub fn with_cache(mut self, cache: impl Cache) -> Self {
+        self.cache = Some(CacheHandle(Arc::new(cache)));
+        self
+    }
+
+    /// Parse an image ID from a raw ID or a Gyazo URL
+    ///
+    /// Accepts the same forms as [`ImageId::parse`], but permalinks must be
+    /// on this client's permalink host: a team client rejects permalinks of
+    /// other teams and of the public service, and vice versa.
+    pub fn parse_image_id(&self, input: &str) -> Result<ImageId, GyazoError> {
+        let image = ImageRef::parse(input)?;
+        match image.host {
+            Some(host) if host != self.permalink_host => Err(GyazoError::InvalidUrl(format!(
+                "{} is not a permalink on {}",
+                input.trim(),
+                self.permalink_host
+            ))),
+            _ => Ok(image.id),
         }
     }
 
+    /// Host of permalinks, the team domain when a team is configured
+    pub fn permalink_host(&self) -> &str {
+        &self.permalink_host
+    }
+
+    /// Permalink of an image, on the team domain when a team is configured
+    pub fn permalink_url(&self, image_id: &ImageId) -> String {
+        format!("https://{}/{}", self.permalink_host, image_id)
+    }
+
+    fn api_url(&self, path: &str) -> Url {
+        self.base_url.join(path).expect("path must be a valid URL")
+    }
+
     async fn request<T: for<'de> Deserialize<'de>>(
         &self,
-        path: &str,
         method: reqwest::Method,
+        url: Url,
         form: Option<Form>,
     ) -> Result<T, GyazoError> {
-        let url = if path == "/api/upload" {
-            self.upload_url
-                .join(path)
-                .expect("path must be a valid URL")
-        } else {
-            self.base_url.join(path).expect("path must be a valid URL")
+        let response = self.execute(method, url, form, HeaderMap::new()).await?;
+        decode_body(response).await
+    }
+
+    /// GET a JSON response through the cache, if one is configured
+    ///
+    /// Fresh entries are returned without a request; stale entries with an
+    /// `ETag` are revalidated with `If-None-Match`.
+    async fn request_cached<T: for<'de> Deserialize<'de>>(
+        &self,
+        url: Url,
+        image_id: &str,
+    ) -> Result<T, GyazoError> {
+        let Some(CacheHandle(cache)) = &self.cache else {
+            return self.request(reqwest::Method::GET, url, None).await;
         };
+        let key = url.to_string();
+        let cached = cache.get(image_id, &key);
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &cached {
+            if cached.is_fresh() {
+                return decode_json(false, None, &cached.body);
+            }
+            if let Some(value) = cached.etag.as_deref().and_then(|etag| etag.parse().ok()) {
+                headers.insert(IF_NONE_MATCH, value);
+            }
+        }
+
+        // Sent unchecked, since 304 is only a success for a conditional request
+        let response = self
+            .execute_unchecked(reqwest::Method::GET, url, None, headers)
+            .await?;
+        let freshness = Freshness::from_headers(response.headers());
+        if let (StatusCode::NOT_MODIFIED, Some(mut cached)) = (response.status(), cached) {
+            cached.fresh_until = freshness.fresh_until();
+            let value = decode_json(false, None, &cached.body);
+            cache.insert(image_id, &key, cached);
+            return value;
+        }
+        let response = check_status(response).await?;
+        let no_content = response.status() == StatusCode::NO_CONTENT;
+        let etag = cache::etag(response.headers());
+        let content_type = content_type(&response);
+        let body = response.bytes().await?;
+        let value = decode_json(no_content, content_type, &body)?;
+        if !matches!(freshness, Freshness::NoStore) {
+            let entry = CacheEntry {
+                body: body.to_vec(),
+                etag,
+                fresh_until: freshness.fresh_until(),
+            };
+            cache.insert(image_id, &key, entry);
+        }
+        Ok(value)
+    }
+
+    /// Send a request and map error statuses, returning the successful response
+    async fn execute(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        form: Option<Form>,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response, GyazoError> {
+        let response = self.execute_unchecked(method, url, form, headers).await?;
+        check_status(response).await
+    }
+
+    /// Send a request, returning the response whatever its status
+    async fn execute_unchecked(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        form: Option<Form>,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response, GyazoError> {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            let endpoint = url.path();
+            let span = tracing::info_span!(
+                "gyazo.request",
+                method = %method,
+                endpoint,
+                image_id = endpoint.strip_prefix("/api/images/"),
+                status = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                response_size = tracing::field::Empty,
+            );
+            self.send(method, url, form, headers).instrument(span).await
+        }
+        #[cfg(not(feature = "tracing"))]
+        self.send(method, url, form, headers).await
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: Url,
+        form: Option<Form>,
+        headers: HeaderMap,
+    ) -> Result<reqwest::Response, GyazoError> {
         let mut request = self
             .client
             .request(method, url)
-            .bearer_auth(&self.access_token);
+            .bearer_auth(self.access_token.expose_secret())
+            .headers(headers);
 
         if let Some(form) = form {
             request = request.multipart(form);
         }
 
-        let response = request.send().await?;
+        let mut request = request.build()?;
+        self.middleware.before(&mut request)?;
+
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
 
-        match response.status() {
-            StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => {
-                Ok(response.json().await?)
+        let response = self.client.execute(request).await;
+        self.middleware.after(response.as_ref());
+        let response = response?;
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("status", response.status().as_u16());
+            span.record("latency_ms", started.elapsed().as_millis() as u64);
+            if let Some(size) = response.content_length() {
+                span.record("response_size", size);
             }
-            StatusCode::BAD_REQUEST => Err(GyazoError::BadRequest),
-            StatusCode::UNAUTHORIZED => Err(GyazoError::Unauthorized),
-            StatusCode::FORBIDDEN => Err(GyazoError::Forbidden),
-            StatusCode::NOT_FOUND => Err(GyazoError::NotFound),
-            StatusCode::UNPROCESSABLE_ENTITY => Err(GyazoError::UnprocessableEntity),
-            StatusCode::TOO_MANY_REQUESTS => Err(GyazoError::RateLimitExceeded),
-            StatusCode::INTERNAL_SERVER_ERROR => Err(GyazoError::InternalServerError),
-            status => {
-                let message = response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "Unknown error".to_string());
-                Err(GyazoError::ApiError { status, message })
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok());
+                tracing::warn!(retry_after, "rate limited by Gyazo API");
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Download the raw bytes of an image file, e.g. [`GyazoImageResponse::url`]
+    ///
+    /// The access token is not sent, since image files are served from a
+    /// different host than the API.
+    pub async fn download(&self, url: &str) -> Result<Vec<u8>, GyazoError> {
+        let url = Url::parse(url).map_err(|e| GyazoError::InvalidUrl(e.to_string()))?;
+        let response = self.client.get(url).send().await?;
+        let response = check_status(response).await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Download an image from any site to upload it again
+    ///
+    /// Stops reading as soon as the body exceeds `max_bytes`.
+    async fn fetch_image(&self, url: Url, max_bytes: usize) -> Result<Vec<u8>, GyazoError> {
+        let response = self.client.get(url.clone()).send().await?;
+        let mut response = check_status(response).await?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !content_type.starts_with("image/") {
+            return Err(GyazoError::InvalidInput(format!(
+                "{} is not an image, content type: {}",
+                url, content_type
+            )));
+        }
+        let too_large =
+            || GyazoError::InvalidInput(format!("image at {} exceeds {} bytes", url, max_bytes));
+        if response
+            .content_length()
+            .is_some_and(|length| length > max_bytes as u64)
+        {
+            return Err(too_large());
+        }
+        let mut imagedata = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if imagedata.len() + chunk.len() > max_bytes {
+                return Err(too_large());
             }
+            imagedata.extend_from_slice(&chunk);
         }
+        Ok(imagedata)
     }
+
     /// Get an image by its ID
     pub async fn get_image(&self, image_id: &str) -> Result<GyazoImageResponse, GyazoError> {
-        let path = format!("/api/images/{}", image_id);
-        self.request(&path, reqwest::Method::GET, None).await
+        let url = self.api_url(&format!("/api/images/{}", image_id));
+        self.request_cached(url, image_id).await
     }
 
     /// Get a list of images
     pub async fn list_images(&self) -> Result<Vec<GyazoImageResponse>, GyazoError> {
-        let path = "/api/images".to_string();
-        self.request(&path, reqwest::Method::GET, None).await
+        let url = self.api_url("/api/images");
+        self.request(reqwest::Method::GET, url, None).await
+    }
+
+    /// Get one page of images along with the pagination headers
+    pub async fn list_images_page(
+        &self,
+        params: &ListImagesParams,
+    ) -> Result<ImagePage, GyazoError> {
+        let mut url = self.api_url("/api/images");
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(page) = params.page {
+                query.append_pair("page", &page.to_string());
+            }
+            if let Some(per_page) = params.per_page {
+                query.append_pair("per_page", &per_page.to_string());
+            }
+        }
+        let response = self
+            .execute(reqwest::Method::GET, url, None, HeaderMap::new())
+            .await?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+        };
+        let total_count = header("X-Total-Count");
+        let current_page = header("X-Current-Page").map(|page| page as u32);
+        let per_page = header("X-Per-Page").map(|per_page| per_page as u32);
+        Ok(ImagePage {
+            images: decode_body(response).await?,
+            total_count,
+            current_page,
+            per_page,
+        })
+    }
+
+    /// Get the user who owns the access token
+    pub async fn get_user(&self) -> Result<UserResponse, GyazoError> {
+        let url = self.api_url("/api/users/me");
+        self.request(reqwest::Method::GET, url, None).await
     }
 
     /// Upload an image
@@ -135,43 +458,239 @@ impl GyazoClient {
         &self,
         param: UploadParams,
     ) -> Result<UploadImageResponse, GyazoError> {
-        let path = "/api/upload";
+        let url = self
+            .upload_url
+            .join("/api/upload")
+            .expect("path must be a valid URL");
         let form = param.into();
-        self.request(path, reqwest::Method::POST, Some(form)).await
+        self.request(reqwest::Method::POST, url, Some(form)).await
     }
 
     /// Delete an image by its ID
+    ///
+    /// Cached responses for the image are dropped, even if it was already
+    /// gone on the server.
     pub async fn delete_image(&self, image_id: &str) -> Result<DeleteImageResponse, GyazoError> {
-        let path = format!("/api/images/{}", image_id);
-        self.request(&path, reqwest::Method::DELETE, None).await
+        let url = self.api_url(&format!("/api/images/{}", image_id));
+        let result = self.request(reqwest::Method::DELETE, url, None).await;
+        if let (Some(CacheHandle(cache)), Ok(_) | Err(GyazoError::NotFound)) =
+            (&self.cache, &result)
+        {
+            cache.remove_image(image_id);
+        }
+        result
+    }
+
+    /// Delete several images, at most `concurrency` at a time
+    ///
+    /// Images that no longer exist count as deleted. See
+    /// [`GyazoClient::delete_many_with`] for a dry run and other options.
+    pub async fn delete_many<I>(&self, image_ids: I, concurrency: usize) -> DeleteReport
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let options = DeleteOptions {
+            concurrency,
+            ..Default::default()
+        };
+        self.delete_many_with(image_ids, &options).await
+    }
+
+    /// Delete several images with the given options
+    pub async fn delete_many_with<I>(&self, image_ids: I, options: &DeleteOptions) -> DeleteReport
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        cleanup::delete_many(self, image_ids, options).await
+    }
+
+    /// Stream images uploaded from now on, polling every `interval`
+    ///
+    /// See [`watch::watch_new_images`] for how new images are detected.
+    pub fn watch_new_images(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<GyazoImageResponse, GyazoError>> + '_ {
+        self.watch_new_images_with(WatchOptions {
+            interval,
+            ..Default::default()
+        })
+    }
+
+    /// Stream newly uploaded images with the given options
+    pub fn watch_new_images_with(
+        &self,
+        options: WatchOptions,
+    ) -> impl Stream<Item = Result<GyazoImageResponse, GyazoError>> + '_ {
+        watch::watch_new_images(self, options)
     }
 
     /// Get oembed data for an image
-    pub async fn get_oembed(&self, url: &str) -> Result<OembedResponse, GyazoError> {
-        if !url.starts_with("https://gyazo.com/") {
-            return Err(GyazoError::InvalidUrl(
-                "URL must start with 'https://gyazo.com/'".to_string(),
-            ));
+    ///
+    /// `image` may be an image ID or any Gyazo URL accepted by [`ImageId::parse`].
+    /// Bare IDs resolve to a permalink on the team domain when a team is
+    /// configured.
+    pub async fn get_oembed(&self, image: &str) -> Result<OembedResponse, GyazoError> {
+        self.get_oembed_with(image, &OembedOptions::default()).await
+    }
+
+    /// Get oembed data for an image, limiting the size of the returned image
+    pub async fn get_oembed_with(
+        &self,
+        image: &str,
+        options: &OembedOptions,
+    ) -> Result<OembedResponse, GyazoError> {
+        let image = ImageRef::parse(image)?;
+        let mut url = self.api_url("/api/oembed");
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("url", &image.permalink_url(&self.permalink_host));
+            if let Some(maxwidth) = options.maxwidth {
+                query.append_pair("maxwidth", &maxwidth.to_string());
+            }
+            if let Some(maxheight) = options.maxheight {
+                query.append_pair("maxheight", &maxheight.to_string());
+            }
+        }
+        self.request_cached(url, image.id.as_str()).await
+    }
+}
+
+/// Map error statuses to [`GyazoError`], passing successful responses through
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, GyazoError> {
+    match response.status() {
+        StatusCode::OK | StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(response),
+        StatusCode::BAD_REQUEST => Err(GyazoError::BadRequest),
+        StatusCode::UNAUTHORIZED => Err(GyazoError::Unauthorized),
+        StatusCode::FORBIDDEN => Err(GyazoError::Forbidden),
+        StatusCode::NOT_FOUND => Err(GyazoError::NotFound),
+        StatusCode::UNPROCESSABLE_ENTITY => Err(GyazoError::UnprocessableEntity),
+        StatusCode::TOO_MANY_REQUESTS => Err(GyazoError::RateLimitExceeded),
+        StatusCode::INTERNAL_SERVER_ERROR => Err(GyazoError::InternalServerError),
+        status => {
+            let message = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            Err(GyazoError::ApiError { status, message })
+        }
+    }
+}
+
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// Decode a successful response body as JSON
+///
+/// An empty body (e.g. `204 No Content`) decodes like JSON `null`, so callers
+/// expecting `()` or `Option<T>` succeed while other types report a parse error.
+async fn decode_body<T: for<'de> Deserialize<'de>>(
+    response: reqwest::Response,
+) -> Result<T, GyazoError> {
+    let no_content = response.status() == StatusCode::NO_CONTENT;
+    let content_type = content_type(&response);
+    let body = response.bytes().await?;
+    decode_json(no_content, content_type, &body)
+}
+
+fn content_type(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(
+    no_content: bool,
+    content_type: Option<String>,
+    body: &[u8],
+) -> Result<T, GyazoError> {
+    let body: &[u8] = if no_content || body.trim_ascii().is_empty() {
+        b"null"
+    } else {
+        if let Some(content_type) = content_type.filter(|ct| !is_json_content_type(ct)) {
+            return Err(GyazoError::UnexpectedContentType {
+                content_type,
+                snippet: body_snippet(body),
+            });
+        }
+        body
+    };
+    serde_json::from_slice(body).map_err(|source| GyazoError::JsonParseError {
+        source,
+        snippet: body_snippet(body),
+    })
+}
+
+fn is_json_content_type(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    mime == "application/json" || mime.ends_with("+json")
+}
+
+pub(crate) fn body_snippet(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body);
+    match text.char_indices().nth(BODY_SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+/// Parameters for [`GyazoClient::list_images_page`]
+#[derive(Default, Clone, Debug)]
+pub struct ListImagesParams {
+    /// Page number, starting at 1
+    pub page: Option<u32>,
+    /// Images per page, up to 100
+    pub per_page: Option<u32>,
+}
+
+/// A page of images from Gyazo API
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ImagePage {
+    pub images: Vec<GyazoImageResponse>,
+    pub total_count: Option<u64>,
+    pub current_page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+impl ImagePage {
+    /// Whether more images are available after this page
+    pub fn has_next_page(&self) -> bool {
+        match (self.total_count, self.current_page, self.per_page) {
+            (Some(total), Some(page), Some(per_page)) => {
+                u64::from(page) * u64::from(per_page) < total
+            }
+            _ => false,
         }
-        let url = format!("/api/oembed?url={}", url);
-        self.request(&url, reqwest::Method::GET, None).await
     }
 }
 
 /// Image response from Gyazo API
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct GyazoImageResponse {
     pub image_id: String,
     pub permalink_url: Option<String>,
     pub thumb_url: Option<String>,
+    /// URL of the original image file
+    pub url: Option<String>,
     #[serde(rename = "type")]
-    pub image_type: String,
+    pub image_type: ImageFormat,
     pub created_at: String,
     pub metadata: ImageMetadata,
     pub ocr: Option<ImageOcr>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct ImageMetadata {
     pub app: Option<String>,
     pub title: Option<String>,
@@ -179,29 +698,48 @@ pub struct ImageMetadata {
     pub desc: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct ImageOcr {
     pub locale: String,
     pub description: String,
 }
 
+/// User response from Gyazo API
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct UserResponse {
+    pub user: User,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct User {
+    pub email: Option<String>,
+    pub name: String,
+    pub profile_image: Option<String>,
+    pub uid: String,
+}
+
 /// Response after uploading an image
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct UploadImageResponse {
     pub image_id: String,
     pub permalink_url: String,
     pub thumb_url: String,
     pub url: String,
     #[serde(rename = "type")]
-    pub image_type: String,
+    pub image_type: ImageFormat,
 }
 
 /// Response after deleting an image
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct DeleteImageResponse {
     pub image_id: String,
     #[serde(rename = "type")]
-    pub image_type: String,
+    pub image_type: ImageFormat,
 }
 
 /// Parameters for uploading an image
@@ -220,9 +758,11 @@ pub struct UploadParams {
 
 impl From<UploadParams> for reqwest::multipart::Form {
     fn from(params: UploadParams) -> Self {
+        let format = ImageFormat::sniff(&params.imagedata).unwrap_or(ImageFormat::Png);
+        let file_name = format!("image.{}", format.extension());
         let mut form = reqwest::multipart::Form::new().part(
             "imagedata",
-            reqwest::multipart::Part::bytes(params.imagedata).file_name("image.png"),
+            reqwest::multipart::Part::bytes(params.imagedata).file_name(file_name),
         );
         form = form.text(
             "access_policy",
@@ -253,6 +793,78 @@ impl From<UploadParams> for reqwest::multipart::Form {
     }
 }
 
+/// Checks applied to the image data by [`UploadParamsBuilder::build`]
+///
+/// The defaults follow Gyazo's limits; raise them for plans with higher
+/// quotas.
+#[derive(Clone, Debug)]
+pub struct UploadLimits {
+    /// Largest accepted image in bytes
+    pub max_bytes: usize,
+    /// Accepted formats, detected from the image data
+    pub allowed_formats: Vec<ImageFormat>,
+    /// Largest accepted width in pixels
+    pub max_width: u32,
+    /// Largest accepted height in pixels
+    pub max_height: u32,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            allowed_formats: vec![
+                ImageFormat::Png,
+                ImageFormat::Jpeg,
+                ImageFormat::Gif,
+                ImageFormat::WebP,
+                ImageFormat::Bmp,
+                ImageFormat::Tiff,
+                ImageFormat::Heic,
+            ],
+            max_width: DEFAULT_MAX_DIMENSION,
+            max_height: DEFAULT_MAX_DIMENSION,
+        }
+    }
+}
+
+impl UploadLimits {
+    /// Check `imagedata` against the limits and return its format
+    ///
+    /// Dimensions are only checked for formats whose header can be read
+    /// without decoding the image.
+    pub fn check(&self, imagedata: &[u8]) -> Result<ImageFormat, GyazoError> {
+        if imagedata.is_empty() {
+            return Err(GyazoError::InvalidInput("image data is empty".to_string()));
+        }
+        if imagedata.len() > self.max_bytes {
+            return Err(GyazoError::InvalidInput(format!(
+                "image is {} bytes, larger than the limit of {} bytes",
+                imagedata.len(),
+                self.max_bytes
+            )));
+        }
+        let format = ImageFormat::sniff(imagedata).ok_or_else(|| {
+            GyazoError::InvalidInput("image data is not in a recognized image format".to_string())
+        })?;
+        if !self.allowed_formats.contains(&format) {
+            return Err(GyazoError::InvalidInput(format!(
+                "{} images are not allowed",
+                format
+            )));
+        }
+        if let Some((width, height)) = format.dimensions(imagedata) {
+            if width > self.max_width || height > self.max_height {
+                return Err(GyazoError::InvalidInput(format!(
+                    "image is {}x{} pixels, larger than the limit of {}x{}",
+                    width, height, self.max_width, self.max_height
+                )));
+            }
+        }
+        Ok(format)
+    }
+}
+
 /// Builder for UploadParams
 #[derive(Debug)]
 pub struct UploadParamsBuilder {
@@ -265,6 +877,10 @@ pub struct UploadParamsBuilder {
     desc: Option<String>,
     created_at: Option<String>,
     collection_id: Option<String>,
+    strip_metadata: bool,
+    limits: UploadLimits,
+    #[cfg(feature = "image-processing")]
+    processed: Option<crate::processing::ProcessedImage>,
 }
 
 impl UploadParamsBuilder {
@@ -279,7 +895,70 @@ impl UploadParamsBuilder {
             desc: None,
             created_at: None,
             collection_id: None,
+            strip_metadata: false,
+            limits: UploadLimits::default(),
+            #[cfg(feature = "image-processing")]
+            processed: None,
+        }
+    }
+
+    /// Fetch an image from another site to re-host it
+    ///
+    /// Uses the HTTP client of `client` and sets `referer_url` to `url`. The
+    /// response must have an `image/*` content type and be at most 10 MiB.
+    pub async fn from_url(client: &GyazoClient, url: &str) -> Result<Self, GyazoError> {
+        Self::from_url_with_limits(client, url, UploadLimits::default()).await
+    }
+
+    /// Like [`from_url`](Self::from_url), reading at most `limits.max_bytes`
+    /// and checking the image against `limits` in [`build`](Self::build)
+    pub async fn from_url_with_limits(
+        client: &GyazoClient,
+        url: &str,
+        limits: UploadLimits,
+    ) -> Result<Self, GyazoError> {
+        let parsed = Url::parse(url).map_err(|e| GyazoError::InvalidUrl(e.to_string()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(GyazoError::InvalidUrl(format!(
+                "only http and https URLs can be fetched: {}",
+                url
+            )));
+        }
+        let imagedata = client.fetch_image(parsed, limits.max_bytes).await?;
+        Ok(Self::new(imagedata).referer_url(url).limits(limits))
+    }
+
+    /// Decode base64 image data; whitespace such as line breaks is ignored
+    pub fn from_base64(encoded: &str) -> Result<Self, GyazoError> {
+        let encoded: String = encoded
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+        let imagedata = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|e| GyazoError::InvalidInput(format!("invalid base64 image data: {}", e)))?;
+        Ok(Self::new(imagedata))
+    }
+
+    /// Decode a base64 `data:image/...` URL, e.g. from a canvas capture
+    pub fn from_data_url(data_url: &str) -> Result<Self, GyazoError> {
+        let invalid =
+            |reason: &str| GyazoError::InvalidInput(format!("invalid data URL: {}", reason));
+        let rest = data_url
+            .strip_prefix("data:")
+            .ok_or_else(|| invalid("missing data: scheme"))?;
+        let (header, data) = rest
+            .split_once(',')
+            .ok_or_else(|| invalid("missing comma"))?;
+        let mut parameters = header.split(';');
+        let media_type = parameters.next().unwrap_or_default();
+        if !media_type.to_ascii_lowercase().starts_with("image/") {
+            return Err(invalid("media type must be image/*"));
+        }
+        if !parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64")) {
+            return Err(invalid("data must be base64-encoded"));
         }
+        Self::from_base64(data)
     }
 
     pub fn access_policy(mut self, access_policy: impl Into<String>) -> Result<Self, GyazoError> {
@@ -337,9 +1016,52 @@ impl UploadParamsBuilder {
         self
     }
 
+    /// Remove EXIF, XMP, ICC profiles, comments and PNG text chunks in
+    /// [`build`](Self::build)
+    ///
+    /// Supports JPEG, PNG and WebP. Pixel data is copied without re-encoding;
+    /// other formats make `build` fail with [`GyazoError::InvalidInput`].
+    pub fn strip_metadata(mut self, strip_metadata: bool) -> Self {
+        self.strip_metadata = strip_metadata;
+        self
+    }
+
+    /// Replace the limits checked by [`build`](Self::build)
+    pub fn limits(mut self, limits: UploadLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Downscale, convert and recompress the image data
+    ///
+    /// The result of the last call is available from
+    /// [`processed_image`](Self::processed_image).
+    #[cfg(feature = "image-processing")]
+    pub fn process(
+        mut self,
+        options: &crate::processing::ProcessOptions,
+    ) -> Result<Self, GyazoError> {
+        let (imagedata, processed) = crate::processing::process(&self.imagedata, options)?;
+        self.imagedata = imagedata;
+        self.processed = Some(processed);
+        Ok(self)
+    }
+
+    /// Final dimensions and size of the image after [`process`](Self::process)
+    #[cfg(feature = "image-processing")]
+    pub fn processed_image(&self) -> Option<&crate::processing::ProcessedImage> {
+        self.processed.as_ref()
+    }
+
     pub fn build(self) -> Result<UploadParams, GyazoError> {
+        let imagedata = if self.strip_metadata {
+            crate::strip::strip_metadata(&self.imagedata)?
+        } else {
+            self.imagedata
+        };
+        self.limits.check(&imagedata)?;
         Ok(UploadParams {
-            imagedata: self.imagedata,
+            imagedata,
             access_policy: self.access_policy,
             metadata_is_public: self.metadata_is_public,
             referer_url: self.referer_url,
@@ -352,8 +1074,16 @@ impl UploadParamsBuilder {
     }
 }
 
+/// Optional parameters for [`GyazoClient::get_oembed_with`]
+#[derive(Default, Clone, Debug)]
+pub struct OembedOptions {
+    pub maxwidth: Option<u32>,
+    pub maxheight: Option<u32>,
+}
+
 /// Oembed response from Gyazo API
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct OembedResponse {
     pub version: String,
     #[serde(rename = "type")]
@@ -363,11 +1093,17 @@ pub struct OembedResponse {
     pub url: String,
     pub width: u32,
     pub height: u32,
+    pub html: Option<String>,
+    pub title: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub thumbnail_width: Option<u32>,
+    pub thumbnail_height: Option<u32>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::image_format::png_header;
     use mockito::Matcher;
 
     #[tokio::test]
@@ -399,9 +1135,10 @@ mod tests {
             .create();
 
         let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
+            access_token: "fake_token".into(),
             base_url: Some(server.url().to_string()),
             upload_url: None,
+            ..Default::default()
         });
         let result = client.get_image("abc123").await;
 
@@ -446,9 +1183,10 @@ mod tests {
             .create();
 
         let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
+            access_token: "fake_token".into(),
             base_url: Some(server.url().to_string()),
             upload_url: None,
+            ..Default::default()
         });
 
         let result = client.list_images().await;
@@ -460,6 +1198,107 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_list_images_page() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/images")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("page".to_string(), "2".to_string()),
+                Matcher::UrlEncoded("per_page".to_string(), "1".to_string()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("X-Total-Count", "3")
+            .with_header("X-Current-Page", "2")
+            .with_header("X-Per-Page", "1")
+            .with_body(
+                r#"[{"image_id": "abc123", "permalink_url": null, "thumb_url": null,
+                "type": "png", "created_at": "2024-08-10 12:00:00",
+                "metadata": {"app": null, "title": null, "url": null, "desc": null},
+                "ocr": null}]"#,
+            )
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            ..Default::default()
+        });
+        let page = client
+            .list_images_page(&ListImagesParams {
+                page: Some(2),
+                per_page: Some(1),
+            })
+            .await?;
+        assert_eq!(page.images.len(), 1);
+        assert_eq!(page.total_count, Some(3));
+        assert_eq!(page.current_page, Some(2));
+        assert!(page.has_next_page());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_user() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/users/me")
+            .match_header("Authorization", Matcher::Regex("Bearer .+".to_string()))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"user": {"email": "user@example.com", "name": "user",
+                "profile_image": "https://example.com/user.png", "uid": "123"}}"#,
+            )
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            ..Default::default()
+        });
+        let user = client.get_user().await?.user;
+        assert_eq!(user.name, "user");
+        assert_eq!(user.uid, "123");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/abc123.png")
+            .match_header("Authorization", Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body([0, 1, 2, 3])
+            .create();
+        server.mock("GET", "/missing.png").with_status(404).create();
+        server.mock("GET", "/stale.png").with_status(304).create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            ..Default::default()
+        });
+        let bytes = client
+            .download(&format!("{}/abc123.png", server.url()))
+            .await?;
+        assert_eq!(bytes, vec![0, 1, 2, 3]);
+        let result = client
+            .download(&format!("{}/missing.png", server.url()))
+            .await;
+        assert!(matches!(result, Err(GyazoError::NotFound)));
+        // An unsolicited 304 has no body to return
+        let result = client
+            .download(&format!("{}/stale.png", server.url()))
+            .await;
+        assert!(matches!(
+            result,
+            Err(GyazoError::ApiError { status, .. }) if status == StatusCode::NOT_MODIFIED
+        ));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_upload_image() -> anyhow::Result<()> {
         let mut server = mockito::Server::new_async().await;
@@ -476,18 +1315,19 @@ mod tests {
         server
             .mock("POST", "/api/upload")
             .match_header("Authorization", Matcher::Regex("Bearer .+".to_string()))
-            .match_body(Matcher::Any)
+            .match_body(Matcher::Regex(r#"filename="image\.png""#.to_string()))
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(mock_response)
             .create();
 
         let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
+            access_token: "fake_token".into(),
             base_url: None,
             upload_url: Some(server.url().to_string()),
+            ..Default::default()
         });
-        let params = UploadParamsBuilder::new(vec![0, 1, 2, 3])
+        let params = UploadParamsBuilder::new(png_header(1, 1, &[]))
             .title("test image")
             .build()?;
         let result = client.upload_image(params).await;
@@ -496,6 +1336,7 @@ mod tests {
         let image = result?;
         assert_eq!(image.image_id, "abc123");
         assert_eq!(image.permalink_url, "https://gyazo.com/abc123".to_string());
+        assert_eq!(image.image_type, ImageFormat::Png);
         Ok(())
     }
 
@@ -518,9 +1359,10 @@ mod tests {
             .create();
 
         let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
+            access_token: "fake_token".into(),
             base_url: Some(server.url().to_string()),
             upload_url: None,
+            ..Default::default()
         });
         let result = client.delete_image("abc123").await;
 
@@ -546,56 +1388,525 @@ mod tests {
         "#;
 
         server
-            .mock("GET", "/api/oembed?url=https://gyazo.com/abc123")
+            .mock("GET", "/api/oembed")
+            .match_query(Matcher::UrlEncoded(
+                "url".to_string(),
+                "https://gyazo.com/abc123".to_string(),
+            ))
             .match_header("Authorization", Matcher::Regex("Bearer .+".to_string()))
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(mock_response)
+            .expect(4)
             .create();
 
         let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
+            access_token: "fake_token".into(),
             base_url: Some(server.url().to_string()),
             upload_url: None,
-        });
-        let result = client.get_oembed("https://gyazo.com/abc123").await;
-
-        assert!(result.is_ok());
-        let oembed = result?;
-        assert_eq!(oembed.version, "1.0");
-        assert_eq!(oembed.image_type, "photo");
-        Ok(())
-    }
-
-    #[tokio::test]
-    async fn test_get_oembed_invalid_url() -> anyhow::Result<()> {
-        let client = GyazoClient::new(GyazoClientOptions {
-            access_token: "fake_token".to_string(),
             ..Default::default()
         });
-        let result = client.get_oembed("https://example.com/abc123").await;
-        assert!(result.is_err());
-        assert_eq!(
-            result.unwrap_err().to_string(),
-            "Invalid url: URL must start with 'https://gyazo.com/'"
-        );
+        for image in [
+            "https://gyazo.com/abc123",
+            "http://gyazo.com/abc123",
+            "gyazo.com/abc123",
+            "abc123",
+        ] {
+            let oembed = client.get_oembed(image).await?;
+            assert_eq!(oembed.version, "1.0");
+            assert_eq!(oembed.image_type, "photo");
+            assert_eq!(oembed.html, None);
+        }
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_upload_params_builder() -> anyhow::Result<()> {
-        let params = UploadParamsBuilder::new(vec![0, 1, 2, 3])
-            .access_policy("anyone")?
-            .metadata_is_public("true")?
-            .referer_url("https://example.com")
-            .app("test app")
-            .title("test image")
-            .desc("test description")
+    async fn test_get_oembed_with_options() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let mock_response = r#"
+        {
+            "version": "1.0",
+            "type": "photo",
+            "provider_name": "Gyazo",
+            "provider_url": "https://gyazo.com",
+            "url": "https://i.gyazo.com/abc123.png",
+            "width": 200,
+            "height": 150,
+            "html": "<img src=\"https://i.gyazo.com/abc123.png\">",
+            "title": "test image",
+            "thumbnail_url": "https://thumb.gyazo.com/thumb/abc123",
+            "thumbnail_width": 100,
+            "thumbnail_height": 75
+        }
+        "#;
+
+        server
+            .mock("GET", "/api/oembed")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(
+                    "url".to_string(),
+                    "https://myteam.gyazo.com/abc123".to_string(),
+                ),
+                Matcher::UrlEncoded("maxwidth".to_string(), "200".to_string()),
+                Matcher::UrlEncoded("maxheight".to_string(), "150".to_string()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(mock_response)
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        });
+        let options = OembedOptions {
+            maxwidth: Some(200),
+            maxheight: Some(150),
+        };
+        let oembed = client
+            .get_oembed_with("https://myteam.gyazo.com/abc123?a=1&b=2#c", &options)
+            .await?;
+        assert_eq!(oembed.width, 200);
+        assert_eq!(oembed.title, Some("test image".to_string()));
+        assert_eq!(oembed.thumbnail_width, Some(100));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_team_client() -> anyhow::Result<()> {
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            team: Some("myteam".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(client.base_url.as_str(), "https://myteam.gyazo.com/");
+        assert_eq!(client.upload_url.as_str(), "https://myteam.gyazo.com/");
+        let image_id = client.parse_image_id("https://myteam.gyazo.com/abc123")?;
+        assert_eq!(image_id.as_str(), "abc123");
+        assert!(client
+            .parse_image_id("https://i.gyazo.com/abc123.png")
+            .is_ok());
+        for other in [
+            "https://otherteam.gyazo.com/abc123",
+            "https://gyazo.com/abc123",
+        ] {
+            assert!(matches!(
+                client.parse_image_id(other),
+                Err(GyazoError::InvalidUrl(_))
+            ));
+        }
+        assert_eq!(
+            client.permalink_url(&image_id),
+            "https://myteam.gyazo.com/abc123"
+        );
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/oembed")
+            .match_query(Matcher::UrlEncoded(
+                "url".to_string(),
+                "https://myteam.gyazo.com/abc123".to_string(),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"version": "1.0", "type": "photo", "provider_name": "Gyazo",
+                "provider_url": "https://gyazo.com", "url": "https://i.gyazo.com/abc123.png",
+                "width": 400, "height": 300}"#,
+            )
+            .create();
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            team: Some("myteam".to_string()),
+            ..Default::default()
+        });
+        let oembed = client.get_oembed("abc123").await?;
+        assert_eq!(oembed.url, "https://i.gyazo.com/abc123.png");
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_new_rejects_invalid_options() {
+        let result = GyazoClient::try_new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            team: Some("my team".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(GyazoError::InvalidInput(_))));
+        let result = GyazoClient::try_new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some("not a url".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(GyazoError::InvalidUrl(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_oembed_invalid_url() -> anyhow::Result<()> {
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            ..Default::default()
+        });
+        let result = client.get_oembed("https://example.com/abc123").await;
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid url: not a Gyazo image URL or ID: https://example.com/abc123"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_span_fields() -> anyhow::Result<()> {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/images/abc123")
+            .with_status(429)
+            .with_header("Retry-After", "30")
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "super_secret_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        });
+        let result = client.get_image("abc123").await;
+        assert!(matches!(result, Err(GyazoError::RateLimitExceeded)));
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+        assert!(output.contains("rate limited by Gyazo API"));
+        assert!(output.contains("retry_after=\"30\""));
+        assert!(output.contains("endpoint=\"/api/images/abc123\""));
+        assert!(output.contains("image_id=\"abc123\""));
+        assert!(output.contains("status=429"));
+        assert!(!output.contains("super_secret_token"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_middleware() -> anyhow::Result<()> {
+        use std::sync::Mutex;
+
+        struct CustomHeader;
+
+        impl Middleware for CustomHeader {
+            fn before(&self, request: &mut reqwest::Request) -> Result<(), GyazoError> {
+                request
+                    .headers_mut()
+                    .insert("X-Audit-Id", "audit-1".parse().expect("valid header"));
+                Ok(())
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl Middleware for Recorder {
+            fn before(&self, request: &mut reqwest::Request) -> Result<(), GyazoError> {
+                self.0.lock().unwrap().push(format!(
+                    "{} {}",
+                    request.method(),
+                    request.url().path()
+                ));
+                Ok(())
+            }
+
+            fn after(&self, response: Result<&reqwest::Response, &reqwest::Error>) {
+                let outcome = match response {
+                    Ok(response) => response.status().as_u16().to_string(),
+                    Err(_) => "error".to_string(),
+                };
+                self.0.lock().unwrap().push(outcome);
+            }
+        }
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("DELETE", "/api/images/abc123")
+            .match_header("X-Audit-Id", "audit-1")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"image_id": "abc123", "type": "png"}"#)
+            .create();
+
+        let recorder = Recorder::default();
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        })
+        .with_middleware(CustomHeader)
+        .with_middleware(recorder.clone());
+        client.delete_image("abc123").await?;
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec!["DELETE /api/images/abc123".to_string(), "200".to_string()]
+        );
+
+        // Transport errors are passed to `after` too
+        let recorder = Recorder::default();
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            ..Default::default()
+        })
+        .with_middleware(recorder.clone());
+        assert!(client.get_image("abc123").await.is_err());
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec!["GET /api/images/abc123".to_string(), "error".to_string()]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_middleware_can_abort_request() {
+        struct Deny;
+
+        impl Middleware for Deny {
+            fn before(&self, _request: &mut reqwest::Request) -> Result<(), GyazoError> {
+                Err(GyazoError::Other("denied by middleware".to_string()))
+            }
+        }
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some("http://127.0.0.1:9".to_string()),
+            upload_url: None,
+            ..Default::default()
+        })
+        .with_middleware(Deny);
+        let result = client.get_image("abc123").await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unexpected error: denied by middleware"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_content_response() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("DELETE", "/api/images/abc123")
+            .with_status(204)
+            .expect(3)
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        });
+        let url = client.api_url("/api/images/abc123");
+        client
+            .request::<()>(reqwest::Method::DELETE, url.clone(), None)
+            .await?;
+        let result: Option<DeleteImageResponse> =
+            client.request(reqwest::Method::DELETE, url, None).await?;
+        assert!(result.is_none());
+        let result = client.delete_image("abc123").await;
+        assert!(matches!(result, Err(GyazoError::JsonParseError { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_html_response() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/images/abc123")
+            .with_status(200)
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body("<html><body>Maintenance</body></html>")
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        });
+        let result = client.get_image("abc123").await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unexpected content type: text/html; charset=utf-8, body: <html><body>Maintenance</body></html>"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_response() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/images/abc123")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(format!(r#"{{"image_id": "{}"#, "a".repeat(300)))
+            .create();
+
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url().to_string()),
+            upload_url: None,
+            ..Default::default()
+        });
+        let result = client.get_image("abc123").await;
+        match result {
+            Err(GyazoError::JsonParseError { snippet, .. }) => {
+                assert!(snippet.starts_with(r#"{"image_id": "aaa"#));
+                assert!(snippet.ends_with("..."));
+                assert_eq!(snippet.chars().count(), BODY_SNIPPET_LEN + 3);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_redacts_access_token() {
+        let options = GyazoClientOptions {
+            access_token: "super_secret_token".into(),
+            ..Default::default()
+        };
+        assert!(!format!("{:?}", options).contains("super_secret_token"));
+        let client = GyazoClient::new(options);
+        assert!(!format!("{:?}", client).contains("super_secret_token"));
+    }
+
+    fn round_trip<T>(value: &T) -> anyhow::Result<serde_json::Value>
+    where
+        T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let json = serde_json::to_value(value)?;
+        assert_eq!(&serde_json::from_value::<T>(json.clone())?, value);
+        Ok(json)
+    }
+
+    #[test]
+    fn test_model_round_trip() -> anyhow::Result<()> {
+        let image = GyazoImageResponse {
+            image_id: "abc123".to_string(),
+            permalink_url: Some("https://gyazo.com/abc123".to_string()),
+            thumb_url: None,
+            url: Some("https://i.gyazo.com/abc123.png".to_string()),
+            image_type: ImageFormat::Png,
+            created_at: "2024-08-10 12:00:00".to_string(),
+            metadata: ImageMetadata {
+                app: Some("Safari".to_string()),
+                title: Some("Example".to_string()),
+                url: None,
+                desc: None,
+            },
+            ocr: Some(ImageOcr {
+                locale: "ja".to_string(),
+                description: "エラー".to_string(),
+            }),
+        };
+        let json = round_trip(&image)?;
+        assert_eq!(json["type"], "png");
+        assert!(json.get("image_type").is_none());
+        round_trip(&ImagePage {
+            images: vec![image],
+            total_count: Some(1),
+            current_page: Some(1),
+            per_page: Some(20),
+        })?;
+
+        let json = round_trip(&UploadImageResponse {
+            image_id: "abc123".to_string(),
+            permalink_url: "https://gyazo.com/abc123".to_string(),
+            thumb_url: "https://thumb.gyazo.com/thumb/abc123".to_string(),
+            url: "https://i.gyazo.com/abc123.png".to_string(),
+            image_type: ImageFormat::Png,
+        })?;
+        assert_eq!(json["type"], "png");
+        round_trip(&DeleteImageResponse {
+            image_id: "abc123".to_string(),
+            image_type: ImageFormat::Png,
+        })?;
+        round_trip(&UserResponse {
+            user: User {
+                email: None,
+                name: "gyazo".to_string(),
+                profile_image: None,
+                uid: "1".to_string(),
+            },
+        })?;
+        let json = round_trip(&OembedResponse {
+            version: "1.0".to_string(),
+            image_type: "photo".to_string(),
+            provider_name: "Gyazo".to_string(),
+            provider_url: "https://gyazo.com".to_string(),
+            url: "https://i.gyazo.com/abc123.png".to_string(),
+            width: 640,
+            height: 480,
+            html: None,
+            title: None,
+            thumbnail_url: None,
+            thumbnail_width: None,
+            thumbnail_height: None,
+        })?;
+        assert_eq!(json["type"], "photo");
+        Ok(())
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_model_json_schema() {
+        let schema = serde_json::to_value(schemars::schema_for!(GyazoImageResponse))
+            .expect("schema serializes");
+        assert!(schema["properties"]["type"].is_object());
+        assert!(schema["properties"].get("image_type").is_none());
+        let required = schema["required"].as_array().expect("required fields");
+        assert!(required.iter().any(|field| field == "image_id"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_params_builder() -> anyhow::Result<()> {
+        let params = UploadParamsBuilder::new(png_header(1, 1, &[]))
+            .access_policy("anyone")?
+            .metadata_is_public("true")?
+            .referer_url("https://example.com")
+            .app("test app")
+            .title("test image")
+            .desc("test description")
             .created_at("2024-08-10 12:00:00")
             .collection_id("test collection")
             .build()?;
 
-        assert_eq!(params.imagedata, vec![0, 1, 2, 3]);
+        assert_eq!(params.imagedata, png_header(1, 1, &[]));
         assert_eq!(params.access_policy, Some("anyone".to_string()));
         assert_eq!(params.metadata_is_public, Some("true".to_string()));
         assert_eq!(params.referer_url, Some("https://example.com".to_string()));
@@ -630,4 +1941,246 @@ mod tests {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_upload_params_builder_from_url() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/cat.png")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body([0, 1, 2, 3])
+            .create();
+        server
+            .mock("GET", "/page.html")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<html></html>")
+            .create();
+        server
+            .mock("GET", "/huge.png")
+            .with_status(200)
+            .with_header("content-type", "image/png")
+            .with_body(vec![0; DEFAULT_MAX_UPLOAD_BYTES + 1])
+            .create();
+        let client = GyazoClient::new(GyazoClientOptions::default());
+
+        let url = format!("{}/cat.png", server.url());
+        let builder = UploadParamsBuilder::from_url(&client, &url).await?;
+        assert_eq!(builder.imagedata, vec![0, 1, 2, 3]);
+        assert_eq!(builder.referer_url, Some(url));
+
+        for path in ["/page.html", "/huge.png"] {
+            let result =
+                UploadParamsBuilder::from_url(&client, &format!("{}{}", server.url(), path)).await;
+            assert!(
+                matches!(result, Err(GyazoError::InvalidInput(_))),
+                "{}",
+                path
+            );
+        }
+        let higher_quota = UploadLimits {
+            max_bytes: DEFAULT_MAX_UPLOAD_BYTES + 1,
+            ..Default::default()
+        };
+        let url = format!("{}/huge.png", server.url());
+        let builder =
+            UploadParamsBuilder::from_url_with_limits(&client, &url, higher_quota).await?;
+        assert_eq!(builder.imagedata.len(), DEFAULT_MAX_UPLOAD_BYTES + 1);
+        assert_eq!(builder.limits.max_bytes, DEFAULT_MAX_UPLOAD_BYTES + 1);
+
+        let result = UploadParamsBuilder::from_url(&client, "file:///etc/passwd").await;
+        assert!(matches!(result, Err(GyazoError::InvalidUrl(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_params_builder_from_base64() -> anyhow::Result<()> {
+        let builder = UploadParamsBuilder::from_base64("AAEC\nAw==")?;
+        assert_eq!(builder.imagedata, vec![0, 1, 2, 3]);
+        assert!(builder.referer_url.is_none());
+        assert!(matches!(
+            UploadParamsBuilder::from_base64("not base64!"),
+            Err(GyazoError::InvalidInput(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_params_builder_from_data_url() -> anyhow::Result<()> {
+        let builder = UploadParamsBuilder::from_data_url("data:image/png;base64,AAECAw==")?;
+        assert_eq!(builder.imagedata, vec![0, 1, 2, 3]);
+
+        for data_url in [
+            "image/png;base64,AAECAw==",
+            "data:text/plain;base64,AAECAw==",
+            "data:image/svg+xml,<svg/>",
+            "data:image/png;base64",
+        ] {
+            let result = UploadParamsBuilder::from_data_url(data_url);
+            assert!(
+                matches!(result, Err(GyazoError::InvalidInput(_))),
+                "{}",
+                data_url
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_params_builder_limits() -> anyhow::Result<()> {
+        let error =
+            |imagedata: Vec<u8>, limits: UploadLimits| match UploadParamsBuilder::new(imagedata)
+                .limits(limits)
+                .build()
+            {
+                Err(GyazoError::InvalidInput(message)) => message,
+                other => panic!("expected InvalidInput, got {:?}", other),
+            };
+        assert_eq!(
+            error(Vec::new(), UploadLimits::default()),
+            "image data is empty"
+        );
+        assert_eq!(
+            error(b"plain text".to_vec(), UploadLimits::default()),
+            "image data is not in a recognized image format"
+        );
+        assert_eq!(
+            error(png_header(20_000, 10, &[]), UploadLimits::default()),
+            "image is 20000x10 pixels, larger than the limit of 16384x16384"
+        );
+        let small = UploadLimits {
+            max_bytes: 16,
+            ..Default::default()
+        };
+        assert_eq!(
+            error(png_header(1, 1, &[]), small),
+            "image is 29 bytes, larger than the limit of 16 bytes"
+        );
+        let png_only = UploadLimits {
+            allowed_formats: vec![ImageFormat::Png],
+            ..Default::default()
+        };
+        assert_eq!(
+            error(b"GIF89a\x01\x00\x01\x00".to_vec(), png_only.clone()),
+            "GIF images are not allowed"
+        );
+
+        let higher_quota = UploadLimits {
+            max_width: 30_000,
+            ..png_only
+        };
+        UploadParamsBuilder::new(png_header(20_000, 10, &[]))
+            .limits(higher_quota)
+            .build()?;
+        Ok(())
+    }
+
+    const IMAGE_JSON: &str = r#"{"image_id": "abc123", "permalink_url": "https://gyazo.com/abc123",
+        "thumb_url": null, "type": "png", "created_at": "2024-08-10 12:00:00",
+        "metadata": {"app": null, "title": "cached", "url": null, "desc": null}, "ocr": null}"#;
+
+    fn cached_client(server: &mockito::Server) -> GyazoClient {
+        GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url()),
+            ..Default::default()
+        })
+        .with_cache(crate::cache::MemoryCache::new(100, Duration::from_secs(60)))
+    }
+
+    #[tokio::test]
+    async fn test_cache_serves_fresh_responses() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let get = server
+            .mock("GET", "/api/images/abc123")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("cache-control", "private, max-age=60")
+            .with_body(IMAGE_JSON)
+            .expect(2)
+            .create();
+        server
+            .mock("DELETE", "/api/images/abc123")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"image_id": "abc123", "type": "png"}"#)
+            .create();
+        let client = cached_client(&server);
+
+        for _ in 0..3 {
+            let image = client.get_image("abc123").await?;
+            assert_eq!(image.metadata.title, Some("cached".to_string()));
+        }
+        // Deleting drops the cached entry, so the next lookup hits the server
+        client.delete_image("abc123").await?;
+        client.get_image("abc123").await?;
+        get.assert();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_revalidates_with_etag() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let revalidated = server
+            .mock("GET", "/api/images/abc123")
+            .match_header("If-None-Match", "\"v1\"")
+            .with_status(304)
+            .with_header("cache-control", "no-cache")
+            .expect(2)
+            .create();
+        let fetched = server
+            .mock("GET", "/api/images/abc123")
+            .match_header("If-None-Match", Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("cache-control", "no-cache")
+            .with_header("etag", "\"v1\"")
+            .with_body(IMAGE_JSON)
+            .expect(1)
+            .create();
+        let client = cached_client(&server);
+
+        for _ in 0..3 {
+            let image = client.get_image("abc123").await?;
+            assert_eq!(image.image_id, "abc123");
+        }
+        fetched.assert();
+        revalidated.assert();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_skips_no_store_and_caches_oembed() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let get = server
+            .mock("GET", "/api/images/abc123")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_header("cache-control", "no-store")
+            .with_body(IMAGE_JSON)
+            .expect(2)
+            .create();
+        let oembed = server
+            .mock("GET", "/api/oembed")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"version": "1.0", "type": "photo", "provider_name": "Gyazo",
+                "provider_url": "https://gyazo.com", "url": "https://i.gyazo.com/abc123.png",
+                "width": 100, "height": 50}"#,
+            )
+            .expect(1)
+            .create();
+        let client = cached_client(&server);
+
+        client.get_image("abc123").await?;
+        client.get_image("abc123").await?;
+        client.get_oembed("abc123").await?;
+        client.get_oembed("https://gyazo.com/abc123").await?;
+        get.assert();
+        oembed.assert();
+        Ok(())
+    }
 }
@@ -0,0 +1,290 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Format of an image file, detected from its leading bytes or reported as
+/// the `type` of an image by Gyazo API
+///
+/// Serialized as the lowercase name Gyazo uses, such as `"png"` or `"jpg"`.
+/// Types this crate does not know are kept as [`ImageFormat::Other`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub enum ImageFormat {
+    Png,
+    #[serde(rename = "jpg", alias = "jpeg")]
+    Jpeg,
+    Gif,
+    WebP,
+    Bmp,
+    #[serde(alias = "tif")]
+    Tiff,
+    Heic,
+    /// MP4 video, never detected by [`sniff`](Self::sniff)
+    Mp4,
+    /// Type reported by Gyazo API that this crate does not know
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl ImageFormat {
+    /// Detect the format from the magic bytes at the start of `data`
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', ..] => Some(ImageFormat::Png),
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(ImageFormat::WebP)
+            }
+            [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+            [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(ImageFormat::Tiff),
+            [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..]
+                if [&b"heic"[..], b"heix", b"mif1", b"msf1"]
+                    .iter()
+                    .any(|heic| brand.starts_with(heic)) =>
+            {
+                Some(ImageFormat::Heic)
+            }
+            _ => None,
+        }
+    }
+
+    /// Name used by Gyazo API
+    pub fn as_str(&self) -> &str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::WebP => "webp",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::Heic => "heic",
+            ImageFormat::Mp4 => "mp4",
+            ImageFormat::Other(name) => name,
+        }
+    }
+
+    /// Usual file extension, without the dot
+    ///
+    /// Other types use their name when it is a plain alphanumeric word and
+    /// `bin` otherwise, so the result is always safe in a file name.
+    pub fn extension(&self) -> &str {
+        match self {
+            ImageFormat::Other(name)
+                if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                "bin"
+            }
+            _ => self.as_str(),
+        }
+    }
+
+    /// MIME type, `application/octet-stream` for other types
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Tiff => "image/tiff",
+            ImageFormat::Heic => "image/heic",
+            ImageFormat::Mp4 => "video/mp4",
+            ImageFormat::Other(_) => "application/octet-stream",
+        }
+    }
+
+    pub fn is_video(&self) -> bool {
+        matches!(self, ImageFormat::Mp4)
+    }
+
+    /// Width and height in pixels read from the file header
+    ///
+    /// Returns `None` for formats whose header is not parsed (TIFF, HEIC) and
+    /// for truncated headers.
+    pub(crate) fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        match self {
+            ImageFormat::Png if data.get(12..16)? == b"IHDR" => {
+                Some((be_u32(data, 16)?, be_u32(data, 20)?))
+            }
+            ImageFormat::Gif => Some((u32::from(le_u16(data, 6)?), u32::from(le_u16(data, 8)?))),
+            ImageFormat::Jpeg => jpeg_dimensions(data),
+            ImageFormat::WebP => webp_dimensions(data),
+            ImageFormat::Bmp => {
+                let width = i32::from_le_bytes(data.get(18..22)?.try_into().ok()?);
+                let height = i32::from_le_bytes(data.get(22..26)?.try_into().ok()?);
+                Some((width.unsigned_abs(), height.unsigned_abs()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Gif => "GIF",
+            ImageFormat::WebP => "WebP",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::Tiff => "TIFF",
+            ImageFormat::Heic => "HEIC",
+            ImageFormat::Mp4 => "MP4",
+            ImageFormat::Other(name) => name,
+        };
+        f.write_str(name)
+    }
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u24(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..at + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+/// Read the frame header (SOFn) that follows the metadata segments
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        let is_frame = (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_frame {
+            let height = be_u16(data, pos + 5)?;
+            let width = be_u16(data, pos + 7)?;
+            return Some((u32::from(width), u32::from(height)));
+        }
+        if marker == 0xDA {
+            return None;
+        }
+        pos += 2 + usize::from(be_u16(data, pos + 2)?);
+    }
+}
+
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        b"VP8 " => Some((
+            u32::from(le_u16(data, 26)? & 0x3FFF),
+            u32::from(le_u16(data, 28)? & 0x3FFF),
+        )),
+        b"VP8L" => {
+            let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        b"VP8X" => Some((le_u24(data, 24)? + 1, le_u24(data, 27)? + 1)),
+        _ => None,
+    }
+}
+
+/// Smallest PNG header with the given size, followed by `tail`
+#[cfg(test)]
+pub(crate) fn png_header(width: u32, height: u32, tail: &[u8]) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[8, 6, 0, 0, 0]);
+    data.extend_from_slice(tail);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encode(format: image::ImageFormat) -> anyhow::Result<Vec<u8>> {
+        let image = image::RgbImage::new(30, 20);
+        let mut output = Cursor::new(Vec::new());
+        image.write_to(&mut output, format)?;
+        Ok(output.into_inner())
+    }
+
+    #[test]
+    fn test_sniff_and_dimensions() -> anyhow::Result<()> {
+        for (encoded, format) in [
+            (encode(image::ImageFormat::Png)?, ImageFormat::Png),
+            (encode(image::ImageFormat::Jpeg)?, ImageFormat::Jpeg),
+            (encode(image::ImageFormat::WebP)?, ImageFormat::WebP),
+        ] {
+            assert_eq!(ImageFormat::sniff(&encoded), Some(format.clone()));
+            assert_eq!(format.dimensions(&encoded), Some((30, 20)), "{}", format);
+        }
+
+        let gif = b"GIF89a\x1e\x00\x14\x00";
+        assert_eq!(ImageFormat::sniff(gif), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::Gif.dimensions(gif), Some((30, 20)));
+        assert_eq!(
+            ImageFormat::sniff(b"\0\0\0\x18ftypheic"),
+            Some(ImageFormat::Heic)
+        );
+        assert_eq!(ImageFormat::sniff(b"<svg></svg>"), None);
+        assert_eq!(ImageFormat::sniff(&[]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_format_serde() -> anyhow::Result<()> {
+        let formats: Vec<ImageFormat> =
+            serde_json::from_str(r#"["png", "jpg", "jpeg", "webp", "mp4", "svg"]"#)?;
+        assert_eq!(
+            formats,
+            vec![
+                ImageFormat::Png,
+                ImageFormat::Jpeg,
+                ImageFormat::Jpeg,
+                ImageFormat::WebP,
+                ImageFormat::Mp4,
+                ImageFormat::Other("svg".to_string()),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&formats)?,
+            r#"["png","jpg","jpg","webp","mp4","svg"]"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_format_helpers() {
+        assert_eq!(ImageFormat::Jpeg.extension(), "jpg");
+        assert_eq!(ImageFormat::Jpeg.mime_type(), "image/jpeg");
+        assert_eq!(ImageFormat::Mp4.mime_type(), "video/mp4");
+        assert!(ImageFormat::Mp4.is_video());
+        assert_eq!(ImageFormat::Other("svg".to_string()).extension(), "svg");
+        assert_eq!(ImageFormat::Other("../x".to_string()).extension(), "bin");
+        assert_eq!(ImageFormat::Other(String::new()).extension(), "bin");
+        assert_eq!(ImageFormat::WebP.to_string(), "WebP");
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_image_format_json_schema() {
+        let schema =
+            serde_json::to_value(schemars::schema_for!(ImageFormat)).expect("schema serializes");
+        let schema = schema.to_string();
+        assert!(schema.contains("\"jpg\""), "{}", schema);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use std::str::FromStr;
+
+use reqwest::Url;
+
+use crate::GyazoError;
+
+pub(crate) const PUBLIC_HOST: &str = "gyazo.com";
+
+/// ID of an image on Gyazo, e.g. `8980c52421e452ac3355ca3e5cfe7a0c`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(String);
+
+impl ImageId {
+    /// Parse an image ID from a raw ID or any Gyazo URL pointing at an image
+    ///
+    /// Accepted forms include `<id>`, `https://gyazo.com/<id>`,
+    /// `http://gyazo.com/<id>`, `gyazo.com/<id>`, `https://i.gyazo.com/<id>.png`,
+    /// `https://thumb.gyazo.com/thumb/<...>/<id>.png` and Gyazo Teams permalinks
+    /// such as `https://myteam.gyazo.com/<id>`. Query strings and fragments are
+    /// ignored.
+    pub fn parse(input: &str) -> Result<Self, GyazoError> {
+        ImageRef::parse(input).map(|image| image.id)
+    }
+
+    /// Wrap an ID that is known to be valid
+    pub(crate) fn new_unchecked(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Permalink of the image on the public Gyazo service
+    ///
+    /// Use [`crate::GyazoClient::permalink_url`] for Gyazo Teams.
+    pub fn permalink_url(&self) -> String {
+        format!("https://{}/{}", PUBLIC_HOST, self.0)
+    }
+}
+
+impl fmt::Display for ImageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ImageId {
+    type Err = GyazoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for ImageId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Image ID together with the permalink host it was referenced by
+#[derive(Debug)]
+pub(crate) struct ImageRef {
+    pub(crate) id: ImageId,
+    /// Permalink host, `None` when the input was a bare ID
+    pub(crate) host: Option<String>,
+}
+
+impl ImageRef {
+    pub(crate) fn parse(input: &str) -> Result<Self, GyazoError> {
+        let input = input.trim();
+        if is_valid_id(input) {
+            return Ok(Self {
+                id: ImageId::new_unchecked(input),
+                host: None,
+            });
+        }
+        let url = if input.contains("://") {
+            Url::parse(input)
+        } else {
+            Url::parse(&format!("https://{}", input))
+        }
+        .map_err(|_| invalid(input))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(invalid(input));
+        }
+        let host = url.host_str().ok_or_else(|| invalid(input))?;
+        let last_segment = url
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+            .ok_or_else(|| invalid(input))?;
+        let (id, permalink_host) = match host.strip_suffix(PUBLIC_HOST) {
+            Some("i." | "thumb.") => {
+                let id = last_segment.split('.').next().unwrap_or_default();
+                (id, None)
+            }
+            Some("" | "www.") => (last_segment, Some(PUBLIC_HOST.to_string())),
+            Some(prefix) if prefix.ends_with('.') => (last_segment, Some(host.to_string())),
+            _ => return Err(invalid(input)),
+        };
+        if !is_valid_id(id) {
+            return Err(invalid(input));
+        }
+        Ok(Self {
+            id: ImageId::new_unchecked(id),
+            host: permalink_host,
+        })
+    }
+
+    /// Permalink of the image, keeping the host it was referenced by
+    ///
+    /// Bare IDs get a permalink on `default_host`.
+    pub(crate) fn permalink_url(&self, default_host: &str) -> String {
+        let host = self.host.as_deref().unwrap_or(default_host);
+        format!("https://{}/{}", host, self.id)
+    }
+}
+
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn invalid(input: &str) -> GyazoError {
+    GyazoError::InvalidUrl(format!("not a Gyazo image URL or ID: {}", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_id() -> anyhow::Result<()> {
+        let id = "8980c52421e452ac3355ca3e5cfe7a0c";
+        for input in [
+            id.to_string(),
+            format!("https://gyazo.com/{}", id),
+            format!("http://gyazo.com/{}", id),
+            format!("gyazo.com/{}", id),
+            format!("https://gyazo.com/{}/", id),
+            format!("https://gyazo.com/{}?token=abc&x=1#top", id),
+            format!("https://i.gyazo.com/{}.png", id),
+            format!("https://thumb.gyazo.com/thumb/200/_abc/{}.jpg", id),
+        ] {
+            assert_eq!(ImageId::parse(&input)?.as_str(), id, "{}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_image_id_invalid() {
+        for input in [
+            "",
+            "https://example.com/abc123",
+            "https://notgyazo.com/abc123",
+            "ftp://gyazo.com/abc123",
+            "https://gyazo.com/",
+            "https://gyazo.com/abc 123",
+        ] {
+            assert!(ImageId::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_permalink_keeps_team_host() -> anyhow::Result<()> {
+        let image = ImageRef::parse("https://myteam.gyazo.com/abc123")?;
+        assert_eq!(
+            image.permalink_url(PUBLIC_HOST),
+            "https://myteam.gyazo.com/abc123"
+        );
+        let image = ImageRef::parse("http://gyazo.com/abc123")?;
+        assert_eq!(
+            image.permalink_url("myteam.gyazo.com"),
+            "https://gyazo.com/abc123"
+        );
+        let image = ImageRef::parse("https://i.gyazo.com/abc123.png")?;
+        assert_eq!(image.permalink_url(PUBLIC_HOST), "https://gyazo.com/abc123");
+        let image = ImageRef::parse("abc123")?;
+        assert_eq!(
+            image.permalink_url("myteam.gyazo.com"),
+            "https://myteam.gyazo.com/abc123"
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,37 @@
+mod api;
+pub mod cache;
+pub mod cleanup;
+mod date;
+pub mod dedup;
+pub mod embed;
+pub mod export;
+mod fake;
+mod fs_util;
 mod gyazo_client;
+mod image_format;
+mod image_id;
+pub mod local_index;
+mod middleware;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "image-processing")]
+pub mod processing;
+pub mod retention;
+mod secret;
+mod strip;
+pub mod sync;
+#[cfg(feature = "test-server")]
+pub mod testing;
+pub mod watch;
 
+pub use api::GyazoApi;
+pub use fake::FakeGyazo;
 pub use gyazo_client::{
     DeleteImageResponse, GyazoClient, GyazoClientOptions, GyazoError, GyazoImageResponse,
-    ImageMetadata, ImageOcr, OembedResponse, UploadImageResponse, UploadParamsBuilder,
+    ImageMetadata, ImageOcr, ImagePage, ListImagesParams, OembedOptions, OembedResponse,
+    UploadImageResponse, UploadLimits, UploadParams, UploadParamsBuilder, User, UserResponse,
 };
+pub use image_format::ImageFormat;
+pub use image_id::ImageId;
+pub use middleware::Middleware;
+pub use secret::SecretToken;
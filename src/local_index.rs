@@ -0,0 +1,446 @@
+//! Offline search over image metadata and OCR text
+//!
+//! [`LocalIndex`] keeps [`GyazoImageResponse`] records from listings or an
+//! [`export`](crate::export) manifest in an inverted index that can be saved
+//! to a JSON file and queried without network access. Text is matched by
+//! words; Japanese and other CJK text, which has no spaces, is matched by
+//! overlapping pairs of characters so any substring of two or more characters
+//! is found.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::date::{sort_key, to_utc};
+use crate::fs_util::{from_json, from_json_lines, to_json, write_atomically};
+use crate::{GyazoError, GyazoImageResponse};
+
+/// Filters for [`LocalIndex::search`]; every filter that is set must match
+#[derive(Clone, Debug, Default)]
+pub struct IndexQuery {
+    /// Words that must appear in any text field, including OCR text
+    pub text: Option<String>,
+    pub title: Option<String>,
+    pub desc: Option<String>,
+    pub app: Option<String>,
+    /// Substring of the URL the image was captured from
+    pub source_url: Option<String>,
+    /// Words that must appear in the OCR text
+    pub ocr: Option<String>,
+    /// Earliest `created_at`, inclusive, e.g. `2024-01-01`; compared in UTC,
+    /// and images whose `created_at` is not a date never match
+    pub created_after: Option<String>,
+    /// Latest `created_at`, exclusive, e.g. `2024-02-01`; compared in UTC
+    pub created_before: Option<String>,
+}
+
+/// Searchable collection of image records
+#[derive(Debug, Default)]
+pub struct LocalIndex {
+    images: BTreeMap<String, GyazoImageResponse>,
+    /// Term to IDs of the images containing it in any text field
+    terms: HashMap<String, HashSet<String>>,
+}
+
+impl LocalIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an index saved with [`save`](Self::save), or an empty index if
+    /// the file does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, GyazoError> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let images: Vec<GyazoImageResponse> = from_json(&contents)?;
+        let mut index = Self::new();
+        index.extend(images);
+        Ok(index)
+    }
+
+    /// Write the indexed records to `path` as a JSON array
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GyazoError> {
+        let path = path.as_ref();
+        let images: Vec<_> = self.images.values().collect();
+        let contents = to_json(&images)?;
+        Ok(write_atomically(path, &contents)?)
+    }
+
+    /// Add the images of an export manifest (`manifest.json` or
+    /// `manifest.ndjson`), returning how many were read
+    pub fn ingest_manifest(&mut self, path: impl AsRef<Path>) -> Result<usize, GyazoError> {
+        let contents = std::fs::read(path)?;
+        let entries: Vec<crate::export::ManifestEntry> =
+            if contents.trim_ascii_start().starts_with(b"[") {
+                from_json(&contents)?
+            } else {
+                from_json_lines(&contents)?
+            };
+        let count = entries.len();
+        self.extend(entries.into_iter().map(|entry| entry.image));
+        Ok(count)
+    }
+
+    /// Add or replace an image
+    pub fn insert(&mut self, image: GyazoImageResponse) {
+        self.remove(&image.image_id);
+        for term in image_terms(&image) {
+            self.terms
+                .entry(term)
+                .or_default()
+                .insert(image.image_id.clone());
+        }
+        self.images.insert(image.image_id.clone(), image);
+    }
+
+    pub fn remove(&mut self, image_id: &str) -> Option<GyazoImageResponse> {
+        let image = self.images.remove(image_id)?;
+        for term in image_terms(&image) {
+            if let Some(ids) = self.terms.get_mut(&term) {
+                ids.remove(image_id);
+                if ids.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+        Some(image)
+    }
+
+    pub fn get(&self, image_id: &str) -> Option<&GyazoImageResponse> {
+        self.images.get(image_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Images matching every filter of `query`, newest first
+    pub fn search(&self, query: &IndexQuery) -> Vec<&GyazoImageResponse> {
+        let mut matches: Vec<_> = match query.text.as_deref().map(tokenize) {
+            Some(terms) if !terms.is_empty() => {
+                let mut postings = terms.iter().map(|term| self.terms.get(term));
+                let Some(Some(first)) = postings.next() else {
+                    return Vec::new();
+                };
+                let mut ids: HashSet<&String> = first.iter().collect();
+                for posting in postings {
+                    let Some(posting) = posting else {
+                        return Vec::new();
+                    };
+                    ids.retain(|id| posting.contains(*id));
+                }
+                ids.into_iter()
+                    .filter_map(|id| self.images.get(id))
+                    .collect()
+            }
+            _ => self.images.values().collect(),
+        };
+        matches.retain(|image| matches_filters(image, query));
+        matches.sort_by_cached_key(|image| std::cmp::Reverse(sort_key(&image.created_at)));
+        matches
+    }
+}
+
+impl Extend<GyazoImageResponse> for LocalIndex {
+    fn extend<I: IntoIterator<Item = GyazoImageResponse>>(&mut self, images: I) {
+        images.into_iter().for_each(|image| self.insert(image));
+    }
+}
+
+fn matches_filters(image: &GyazoImageResponse, query: &IndexQuery) -> bool {
+    let metadata = &image.metadata;
+    let ocr = image.ocr.as_ref().map(|ocr| ocr.description.as_str());
+    let words = |filter: &Option<String>, field: Option<&str>| match filter {
+        Some(filter) => field.is_some_and(|field| contains_terms(field, filter)),
+        None => true,
+    };
+    let created_at = to_utc(&image.created_at);
+    let compare =
+        |bound: &str, ordering: fn(&String, &String) -> bool| match (&created_at, to_utc(bound)) {
+            (Some(created_at), Some(bound)) => ordering(created_at, &bound),
+            _ => false,
+        };
+    words(&query.title, metadata.title.as_deref())
+        && words(&query.desc, metadata.desc.as_deref())
+        && words(&query.app, metadata.app.as_deref())
+        && words(&query.ocr, ocr)
+        && query.source_url.as_deref().is_none_or(|url| {
+            metadata
+                .url
+                .as_deref()
+                .is_some_and(|source| source.to_lowercase().contains(&url.to_lowercase()))
+        })
+        && query
+            .created_after
+            .as_deref()
+            .is_none_or(|after| compare(after, String::ge))
+        && query
+            .created_before
+            .as_deref()
+            .is_none_or(|before| compare(before, String::lt))
+}
+
+fn contains_terms(field: &str, filter: &str) -> bool {
+    let field: HashSet<String> = tokenize(field).into_iter().collect();
+    tokenize(filter).iter().all(|term| field.contains(term))
+}
+
+fn image_terms(image: &GyazoImageResponse) -> HashSet<String> {
+    let metadata = &image.metadata;
+    [
+        metadata.title.as_deref(),
+        metadata.desc.as_deref(),
+        metadata.app.as_deref(),
+        metadata.url.as_deref(),
+        image.ocr.as_ref().map(|ocr| ocr.description.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(tokenize)
+    .collect()
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana and Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF // Hangul
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+    )
+}
+
+/// Lowercase words, with CJK runs split into overlapping character pairs
+fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut word = String::new();
+    let mut cjk: Vec<char> = Vec::new();
+    let flush_cjk = |cjk: &mut Vec<char>, terms: &mut Vec<String>| {
+        match cjk.len() {
+            0 => {}
+            1 => terms.push(cjk[0].to_string()),
+            _ => terms.extend(cjk.windows(2).map(|pair| pair.iter().collect())),
+        }
+        cjk.clear();
+    };
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                terms.push(std::mem::take(&mut word));
+            }
+            cjk.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk, &mut terms);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_cjk(&mut cjk, &mut terms);
+            if !word.is_empty() {
+                terms.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_cjk(&mut cjk, &mut terms);
+    if !word.is_empty() {
+        terms.push(word);
+    }
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::fixtures;
+    use crate::{ImageMetadata, ImageOcr};
+
+    fn image(id: &str, created_at: &str, title: &str, url: &str, ocr: &str) -> GyazoImageResponse {
+        GyazoImageResponse {
+            image_id: id.to_string(),
+            created_at: created_at.to_string(),
+            metadata: ImageMetadata {
+                app: Some("Google Chrome".to_string()),
+                title: Some(title.to_string()),
+                url: Some(url.to_string()),
+                desc: None,
+            },
+            ocr: Some(ImageOcr {
+                locale: "ja".to_string(),
+                description: ocr.to_string(),
+            }),
+            ..fixtures::image()
+        }
+    }
+
+    fn index() -> LocalIndex {
+        let mut index = LocalIndex::new();
+        index.extend([
+            image(
+                "a",
+                "2024-01-05 10:00:00",
+                "Build failure",
+                "https://ci.example.com/build/1",
+                "error: linker failed",
+            ),
+            image(
+                "b",
+                "2024-02-10T09:00:00+0900",
+                "Design review",
+                "https://figma.com/file/2",
+                "スクリーンショットの共有",
+            ),
+            image(
+                "c",
+                "2024-03-01 08:00:00",
+                "Another build",
+                "https://ci.example.com/build/3",
+                "all tests passed",
+            ),
+        ]);
+        index
+    }
+
+    fn ids(results: Vec<&GyazoImageResponse>) -> Vec<&str> {
+        results
+            .iter()
+            .map(|image| image.image_id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let index = index();
+        let search = |query: IndexQuery| ids(index.search(&query));
+
+        assert_eq!(search(IndexQuery::default()), ["c", "b", "a"]);
+        assert_eq!(
+            search(IndexQuery {
+                text: Some("BUILD".to_string()),
+                ..Default::default()
+            }),
+            ["c", "a"]
+        );
+        assert_eq!(
+            search(IndexQuery {
+                text: Some("build linker".to_string()),
+                ..Default::default()
+            }),
+            ["a"]
+        );
+        assert_eq!(
+            search(IndexQuery {
+                ocr: Some("ショット".to_string()),
+                ..Default::default()
+            }),
+            ["b"]
+        );
+        assert_eq!(
+            search(IndexQuery {
+                source_url: Some("ci.example.com".to_string()),
+                created_after: Some("2024-02-01".to_string()),
+                ..Default::default()
+            }),
+            ["c"]
+        );
+        assert_eq!(
+            search(IndexQuery {
+                app: Some("chrome".to_string()),
+                created_before: Some("2024-02-11".to_string()),
+                ..Default::default()
+            }),
+            ["b", "a"]
+        );
+        assert!(search(IndexQuery {
+            title: Some("failure".to_string()),
+            ocr: Some("passed".to_string()),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_search_compares_created_at_in_utc() {
+        let mut index = index();
+        // 2024-02-09 23:00:00 UTC, so before "b" and inside February 9th
+        index.insert(image("d", "2024-02-10T08:00:00+09:00", "Early", "", ""));
+        index.insert(image("e", "1707523200", "Unix seconds", "", ""));
+        let search = |query: IndexQuery| ids(index.search(&query));
+
+        assert_eq!(
+            search(IndexQuery {
+                created_after: Some("2024-02-01".to_string()),
+                ..Default::default()
+            }),
+            ["c", "b", "d"]
+        );
+        assert_eq!(
+            search(IndexQuery {
+                created_after: Some("2024-02-09".to_string()),
+                created_before: Some("2024-02-10".to_string()),
+                ..Default::default()
+            }),
+            ["d"]
+        );
+    }
+
+    #[test]
+    fn test_replace_remove_and_persist() -> anyhow::Result<()> {
+        let mut index = index();
+        index.insert(image("a", "2024-01-05 10:00:00", "Renamed", "", ""));
+        let query = IndexQuery {
+            text: Some("linker".to_string()),
+            ..Default::default()
+        };
+        assert!(index.search(&query).is_empty());
+        assert!(index.remove("b").is_some());
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("index.json");
+        index.save(&path)?;
+        let reopened = LocalIndex::open(&path)?;
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(
+            ids(reopened.search(&IndexQuery {
+                text: Some("renamed".to_string()),
+                ..Default::default()
+            })),
+            ["a"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ingest_manifest() -> anyhow::Result<()> {
+        use crate::export::{export, ExportOptions, ManifestFormat};
+        use crate::{FakeGyazo, GyazoApi, UploadParamsBuilder};
+
+        let fake = FakeGyazo::new();
+        let params = UploadParamsBuilder::new(crate::image_format::png_header(1, 1, &[]))
+            .title("exported screenshot")
+            .build()?;
+        fake.upload_image(params).await?;
+        let dir = tempfile::tempdir()?;
+        for format in [ManifestFormat::Json, ManifestFormat::Ndjson] {
+            let options = ExportOptions {
+                manifest_format: format,
+                ..Default::default()
+            };
+            export(&fake, dir.path(), &options).await?;
+        }
+
+        for manifest in ["manifest.json", "manifest.ndjson"] {
+            let mut index = LocalIndex::new();
+            assert_eq!(index.ingest_manifest(dir.path().join(manifest))?, 1);
+            let query = IndexQuery {
+                title: Some("screenshot".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(index.search(&query).len(), 1);
+        }
+        Ok(())
+    }
+}
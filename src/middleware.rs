@@ -0,0 +1,61 @@
+use std::fmt;
+use std::sync::Arc;
+
+use reqwest::{Request, Response};
+
+use crate::GyazoError;
+
+/// Hooks run around every request sent by [`crate::GyazoClient`]
+///
+/// `before` hooks run in the order the middleware was added and may modify
+/// the outgoing request or abort it by returning an error. `after` hooks run
+/// in reverse order once the response headers have arrived, or with the error
+/// when the request could not be sent.
+///
+/// Hooks are synchronous and only observe the exchange: they cannot retry a
+/// request, replace the response or read its body. When a `before` hook
+/// aborts the request, no `after` hook runs.
+pub trait Middleware: Send + Sync + 'static {
+    /// Called before the request is sent
+    fn before(&self, request: &mut Request) -> Result<(), GyazoError> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called with the response before its body is read, or with the error
+    /// when no response was received, e.g. on a timeout
+    fn after(&self, response: Result<&Response, &reqwest::Error>) {
+        let _ = response;
+    }
+}
+
+/// Ordered list of middleware configured on a client
+#[derive(Clone, Default)]
+pub(crate) struct MiddlewareStack(Vec<Arc<dyn Middleware>>);
+
+impl MiddlewareStack {
+    pub(crate) fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub(crate) fn before(&self, request: &mut Request) -> Result<(), GyazoError> {
+        self.0
+            .iter()
+            .try_for_each(|middleware| middleware.before(request))
+    }
+
+    pub(crate) fn after(&self, response: Result<&Response, &reqwest::Error>) {
+        self.0
+            .iter()
+            .rev()
+            .for_each(|middleware| middleware.after(response));
+    }
+}
+
+impl fmt::Debug for MiddlewareStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareStack")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
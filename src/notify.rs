@@ -0,0 +1,498 @@
+//! Forwarding images to chat webhooks
+//!
+//! A [`Notifier`] renders a [`Template`] for each image and posts it to Slack
+//! compatible incoming webhooks, Discord webhooks or any HTTP endpoint.
+//! [`Notifier::forward`] announces every image of a stream such as
+//! [`crate::GyazoClient::watch_new_images`].
+
+use std::fmt;
+
+use futures_util::{Stream, StreamExt};
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+use crate::{GyazoError, GyazoImageResponse, ImageId};
+
+/// Longest message Discord accepts
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+/// Where a [`Notifier`] posts messages
+///
+/// Webhook URLs embed their credentials, so `Debug` output only shows their
+/// host, and header values are hidden.
+#[derive(Clone)]
+pub enum NotifyTarget {
+    /// Slack incoming webhook URL, or any service accepting `{"text": ...}`
+    Slack(String),
+    /// Discord webhook URL; mentions in the message are not resolved
+    Discord(String),
+    /// Any endpoint, receiving `{"text": ..., "image": ...}` with the full
+    /// image response
+    Http { url: String, headers: HeaderMap },
+}
+
+impl NotifyTarget {
+    /// Kind and host of the target, safe to log
+    fn describe(&self) -> String {
+        match self {
+            NotifyTarget::Slack(url) => format!("Slack webhook {}", host(url)),
+            NotifyTarget::Discord(url) => format!("Discord webhook {}", host(url)),
+            NotifyTarget::Http { url, .. } => format!("HTTP endpoint {}", host(url)),
+        }
+    }
+}
+
+impl fmt::Debug for NotifyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyTarget::Slack(url) => f.debug_tuple("Slack").field(&redact(url)).finish(),
+            NotifyTarget::Discord(url) => f.debug_tuple("Discord").field(&redact(url)).finish(),
+            NotifyTarget::Http { url, headers } => {
+                let headers: Vec<_> = headers
+                    .keys()
+                    .map(|name| format!("{}: [REDACTED]", name))
+                    .collect();
+                f.debug_struct("Http")
+                    .field("url", &redact(url))
+                    .field("headers", &headers)
+                    .finish()
+            }
+        }
+    }
+}
+
+/// Message text with placeholders replaced by image fields
+///
+/// Supported placeholders are `{title}`, `{desc}`, `{app}`, `{permalink}`,
+/// `{url}`, `{image_id}`, `{created_at}`, `{source_url}` and `{ocr}`, an
+/// excerpt of the OCR text. Missing fields render as empty strings and
+/// unknown placeholders are left as they are.
+#[derive(Clone, Debug)]
+pub struct Template {
+    pub text: String,
+    /// Maximum number of characters of OCR text in `{ocr}`
+    pub ocr_excerpt_len: usize,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::new("New image on Gyazo: {title} {permalink}")
+    }
+}
+
+impl Template {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ocr_excerpt_len: 200,
+        }
+    }
+
+    pub fn render(&self, image: &GyazoImageResponse) -> String {
+        self.render_escaped(image, |value| value.to_string())
+    }
+
+    /// Render with `escape` applied to every substituted value
+    fn render_escaped(
+        &self,
+        image: &GyazoImageResponse,
+        escape: impl Fn(&str) -> String,
+    ) -> String {
+        let metadata = &image.metadata;
+        let permalink = image
+            .permalink_url
+            .clone()
+            .unwrap_or_else(|| ImageId::new_unchecked(&image.image_id).permalink_url());
+        let ocr = image
+            .ocr
+            .as_ref()
+            .map(|ocr| excerpt(&ocr.description, self.ocr_excerpt_len))
+            .unwrap_or_default();
+        let fields = [
+            ("title", metadata.title.as_deref().unwrap_or_default()),
+            ("desc", metadata.desc.as_deref().unwrap_or_default()),
+            ("app", metadata.app.as_deref().unwrap_or_default()),
+            ("permalink", &permalink),
+            ("url", image.url.as_deref().unwrap_or_default()),
+            ("image_id", &image.image_id),
+            ("created_at", &image.created_at),
+            ("source_url", metadata.url.as_deref().unwrap_or_default()),
+            ("ocr", &ocr),
+        ];
+
+        let mut rendered = String::with_capacity(self.text.len());
+        let mut rest = self.text.as_str();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest.find('}').and_then(|end| {
+                let (_, value) = fields.iter().find(|(name, _)| *name == &rest[1..end])?;
+                Some((end, value))
+            });
+            match value {
+                Some((end, value)) => {
+                    rendered.push_str(&escape(value));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// Posts messages about images to webhooks
+#[derive(Clone, Default)]
+pub struct Notifier {
+    client: Client,
+    targets: Vec<NotifyTarget>,
+    template: Template,
+}
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Notifier")
+            .field("targets", &self.targets)
+            .field("template", &self.template)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a preconfigured HTTP client, e.g. with a proxy or timeouts
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn target(mut self, target: NotifyTarget) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    pub fn template(mut self, template: Template) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Post one message about `image` to every target
+    ///
+    /// Every target is tried; the first failure is returned as
+    /// [`GyazoError::NotifyFailed`].
+    pub async fn notify(&self, image: &GyazoImageResponse) -> Result<(), GyazoError> {
+        let mut result = Ok(());
+        for target in &self.targets {
+            let sent = self.send(target, image).await;
+            if result.is_ok() {
+                result = sent;
+            }
+        }
+        result
+    }
+
+    /// Notify about every image of `images` until the stream ends
+    ///
+    /// Failed notifications are logged with the `tracing` feature and do not
+    /// stop forwarding; only an error of the stream itself is returned.
+    pub async fn forward<S>(&self, images: S) -> Result<(), GyazoError>
+    where
+        S: Stream<Item = Result<GyazoImageResponse, GyazoError>>,
+    {
+        self.forward_with(images, |_image, _error| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(image_id = %_image.image_id, error = %_error, "notification failed");
+        })
+        .await
+    }
+
+    /// Like [`Notifier::forward`], passing every failed notification to
+    /// `on_failure`
+    pub async fn forward_with<S, F>(&self, images: S, mut on_failure: F) -> Result<(), GyazoError>
+    where
+        S: Stream<Item = Result<GyazoImageResponse, GyazoError>>,
+        F: FnMut(&GyazoImageResponse, GyazoError),
+    {
+        let mut images = std::pin::pin!(images);
+        while let Some(image) = images.next().await {
+            let image = image?;
+            if let Err(error) = self.notify(&image).await {
+                on_failure(&image, error);
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        target: &NotifyTarget,
+        image: &GyazoImageResponse,
+    ) -> Result<(), GyazoError> {
+        let (url, headers, body) = match target {
+            NotifyTarget::Slack(url) => {
+                let text = self.template.render_escaped(image, escape_slack);
+                (url, HeaderMap::new(), json!({ "text": text }))
+            }
+            NotifyTarget::Discord(url) => {
+                let content = truncate(&self.template.render(image), DISCORD_MAX_CONTENT);
+                let body = json!({
+                    "content": content,
+                    "allowed_mentions": { "parse": Value::Array(Vec::new()) },
+                });
+                (url, HeaderMap::new(), body)
+            }
+            NotifyTarget::Http { url, headers } => {
+                let body = json!({
+                    "text": self.template.render(image),
+                    "image": image,
+                });
+                (url, headers.clone(), body)
+            }
+        };
+        let failed = |status, message| GyazoError::NotifyFailed {
+            target: target.describe(),
+            status,
+            message,
+        };
+        let response = self
+            .client
+            .post(url)
+            .headers(headers)
+            .json(&body)
+            .send()
+            .await
+            // The error message would include the URL and its credentials
+            .map_err(|e| failed(None, e.without_url().to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let message = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(failed(Some(status), message))
+    }
+}
+
+/// Host of `url`, or a placeholder when it cannot be parsed
+fn host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "(invalid URL)".to_string())
+}
+
+/// `url` with everything after the host hidden
+fn redact(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| Some(format!("{}://{}/[REDACTED]", url.scheme(), url.host_str()?)))
+        .unwrap_or_else(|| "[REDACTED]".to_string())
+}
+
+/// Whitespace-collapsed prefix of `text` of at most `max_chars` characters
+fn excerpt(text: &str, max_chars: usize) -> String {
+    truncate(
+        &text.split_whitespace().collect::<Vec<_>>().join(" "),
+        max_chars,
+    )
+}
+
+/// `text` cut to at most `max_chars` characters, ending with `…` when cut
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().nth(max_chars).is_none() {
+        return text.to_string();
+    }
+    match max_chars.checked_sub(1) {
+        Some(kept) => {
+            let end = text
+                .char_indices()
+                .nth(kept)
+                .map_or(text.len(), |(end, _)| end);
+            format!("{}…", &text[..end])
+        }
+        None => String::new(),
+    }
+}
+
+/// Slack treats `&`, `<` and `>` as control characters in message text
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::fixtures;
+    use crate::{ImageMetadata, ImageOcr};
+    use mockito::Matcher;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+    use reqwest::StatusCode;
+
+    fn image() -> GyazoImageResponse {
+        GyazoImageResponse {
+            metadata: ImageMetadata {
+                app: Some("Chrome".to_string()),
+                title: Some("<Deploy> & rollback".to_string()),
+                url: None,
+                desc: None,
+            },
+            ocr: Some(ImageOcr {
+                locale: "en".to_string(),
+                description: "@everyone  build\nfailed at step 3".to_string(),
+            }),
+            ..fixtures::image()
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let template = Template {
+            ocr_excerpt_len: 12,
+            ..Template::new("{title} ({app}) {permalink} {ocr} {unknown} {desc}{")
+        };
+        assert_eq!(
+            template.render(&image()),
+            "<Deploy> & rollback (Chrome) https://gyazo.com/abc123 @everyone b… {unknown} {"
+        );
+        assert_eq!(excerpt("ab  cd", 5), "ab cd");
+        assert_eq!(excerpt("", 5), "");
+        assert_eq!(truncate("ab\ncdef", 4), "ab\n…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+
+    #[tokio::test]
+    async fn test_notify_targets() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let slack = server
+            .mock("POST", "/slack")
+            .match_body(Matcher::Json(json!({
+                "text": "&lt;Deploy&gt; &amp; rollback https://gyazo.com/abc123",
+            })))
+            .create_async()
+            .await;
+        let discord = server
+            .mock("POST", "/discord")
+            .match_body(Matcher::Json(json!({
+                "content": "@everyone build failed at step 3",
+                "allowed_mentions": { "parse": [] },
+            })))
+            .with_status(204)
+            .create_async()
+            .await;
+        let http = server
+            .mock("POST", "/hook")
+            .match_header("authorization", "Bearer secret")
+            .match_body(Matcher::PartialJson(json!({
+                "text": "abc123",
+                "image": { "image_id": "abc123", "type": "png" },
+            })))
+            .create_async()
+            .await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        let base = server.url();
+        let slack_notifier = Notifier::new()
+            .template(Template::new("{title} {permalink}"))
+            .target(NotifyTarget::Slack(format!("{}/slack", base)));
+        slack_notifier.notify(&image()).await?;
+        let notifier = Notifier::new()
+            .template(Template::new("{ocr}"))
+            .target(NotifyTarget::Discord(format!("{}/discord", base)));
+        notifier.notify(&image()).await?;
+        let notifier = Notifier::new()
+            .template(Template::new("{image_id}"))
+            .target(NotifyTarget::Http {
+                url: format!("{}/hook", base),
+                headers,
+            });
+        notifier.notify(&image()).await?;
+
+        slack.assert_async().await;
+        discord.assert_async().await;
+        http.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_forward_continues_after_failure() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let failing = server
+            .mock("POST", "/slack")
+            .with_status(500)
+            .with_body("invalid_payload")
+            .expect(2)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/discord")
+            .expect(2)
+            .create_async()
+            .await;
+        let notifier = Notifier::new()
+            .target(NotifyTarget::Slack(format!("{}/slack", server.url())))
+            .target(NotifyTarget::Discord(format!("{}/discord", server.url())));
+
+        let images = futures_util::stream::iter([
+            Ok(image()),
+            Ok(image()),
+            Err(GyazoError::Unauthorized),
+            Ok(image()),
+        ]);
+        let mut failures = Vec::new();
+        let result = notifier
+            .forward_with(images, |_, error| failures.push(error))
+            .await;
+        // Stream errors end forwarding, notification failures do not
+        assert!(matches!(result, Err(GyazoError::Unauthorized)));
+        assert_eq!(failures.len(), 2);
+        assert!(failures.iter().all(|error| matches!(
+            error,
+            GyazoError::NotifyFailed { target, status, message }
+                if target == "Slack webhook 127.0.0.1"
+                    && *status == Some(StatusCode::INTERNAL_SERVER_ERROR)
+                    && message == "invalid_payload"
+        )));
+        failing.assert_async().await;
+        ok.assert_async().await;
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_redacts_urls_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        let notifier = Notifier::new()
+            .target(NotifyTarget::Slack(
+                "https://hooks.slack.com/services/T000/B000/XXXX".to_string(),
+            ))
+            .target(NotifyTarget::Http {
+                url: "https://example.com/hook?token=secret".to_string(),
+                headers,
+            });
+        let debug = format!("{:?}", notifier);
+        assert!(
+            debug.contains("https://hooks.slack.com/[REDACTED]"),
+            "{}",
+            debug
+        );
+        assert!(debug.contains("authorization: [REDACTED]"), "{}", debug);
+        assert!(
+            !debug.contains("XXXX") && !debug.contains("secret"),
+            "{}",
+            debug
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Client-side image preprocessing before upload
+//!
+//! Enabled by the `image-processing` feature. [`UploadParamsBuilder::process`]
+//! decodes the image, optionally downscales it and converts it to another
+//! format, and re-encodes it. Re-encoding never copies EXIF, GPS or other
+//! metadata into the output.
+//!
+//! [`UploadParamsBuilder::process`]: crate::UploadParamsBuilder::process
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+
+use crate::GyazoError;
+
+/// Format written by [`ProcessOptions`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetFormat {
+    /// PNG at the highest compression level
+    Png,
+    /// JPEG at [`ProcessOptions::jpeg_quality`]; transparency is dropped
+    Jpeg,
+    /// Lossless WebP
+    WebP,
+}
+
+impl TargetFormat {
+    fn from_source(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Jpeg => TargetFormat::Jpeg,
+            ImageFormat::WebP => TargetFormat::WebP,
+            _ => TargetFormat::Png,
+        }
+    }
+}
+
+/// How to preprocess an image before upload
+#[derive(Clone, Debug)]
+pub struct ProcessOptions {
+    /// Downscale so neither side exceeds this many pixels, keeping the aspect
+    /// ratio; smaller images are never enlarged
+    pub max_dimension: Option<u32>,
+    /// Output format, or the source format if `None`
+    pub format: Option<TargetFormat>,
+    /// JPEG quality from 1 to 100
+    pub jpeg_quality: u8,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            max_dimension: None,
+            format: None,
+            jpeg_quality: 85,
+        }
+    }
+}
+
+/// Result of preprocessing an image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessedImage {
+    pub format: TargetFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the image before processing in bytes
+    pub original_size: usize,
+    /// Size of the processed image in bytes
+    pub size: usize,
+}
+
+pub(crate) fn process(
+    imagedata: &[u8],
+    options: &ProcessOptions,
+) -> Result<(Vec<u8>, ProcessedImage), GyazoError> {
+    let invalid =
+        |e: image::ImageError| GyazoError::InvalidInput(format!("cannot decode image: {}", e));
+    let source_format = image::guess_format(imagedata).map_err(invalid)?;
+    let mut image =
+        image::load_from_memory_with_format(imagedata, source_format).map_err(invalid)?;
+    if let Some(max) = options.max_dimension {
+        if image.width() > max || image.height() > max {
+            image = image.resize(max, max, FilterType::Lanczos3);
+        }
+    }
+
+    let format = options
+        .format
+        .unwrap_or_else(|| TargetFormat::from_source(source_format));
+    let mut output = Cursor::new(Vec::new());
+    let encoded = match format {
+        TargetFormat::Png => image.write_with_encoder(PngEncoder::new_with_quality(
+            &mut output,
+            CompressionType::Best,
+            PngFilterType::Adaptive,
+        )),
+        TargetFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(
+            JpegEncoder::new_with_quality(&mut output, options.jpeg_quality.clamp(1, 100)),
+        ),
+        TargetFormat::WebP => {
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            image.write_with_encoder(WebPEncoder::new_lossless(&mut output))
+        }
+    };
+    encoded.map_err(|e| GyazoError::Other(format!("cannot encode image: {}", e)))?;
+
+    let output = output.into_inner();
+    let processed = ProcessedImage {
+        format,
+        width: image.width(),
+        height: image.height(),
+        original_size: imagedata.len(),
+        size: output.len(),
+    };
+    Ok((output, processed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UploadParamsBuilder;
+    use image::{Rgb, RgbImage};
+
+    fn png(width: u32, height: u32) -> anyhow::Result<Vec<u8>> {
+        let image = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 128]));
+        let mut output = Cursor::new(Vec::new());
+        image.write_to(&mut output, ImageFormat::Png)?;
+        Ok(output.into_inner())
+    }
+
+    #[test]
+    fn test_downscale_and_convert() -> anyhow::Result<()> {
+        let builder = UploadParamsBuilder::new(png(400, 200)?).process(&ProcessOptions {
+            max_dimension: Some(100),
+            format: Some(TargetFormat::Jpeg),
+            ..Default::default()
+        })?;
+        let processed = builder
+            .processed_image()
+            .cloned()
+            .expect("image was processed");
+        assert_eq!((processed.width, processed.height), (100, 50));
+        assert_eq!(processed.format, TargetFormat::Jpeg);
+
+        let params = builder.build()?;
+        assert_eq!(params.imagedata.len(), processed.size);
+        assert_eq!(image::guess_format(&params.imagedata)?, ImageFormat::Jpeg);
+        Ok(())
+    }
+
+    #[test]
+    fn test_small_image_keeps_size_and_format() -> anyhow::Result<()> {
+        let (output, processed) = process(
+            &png(20, 10)?,
+            &ProcessOptions {
+                max_dimension: Some(100),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!((processed.width, processed.height), (20, 10));
+        assert_eq!(image::guess_format(&output)?, ImageFormat::Png);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strips_exif() -> anyhow::Result<()> {
+        let image = RgbImage::from_pixel(8, 8, Rgb([200, 10, 10]));
+        let mut jpeg = Cursor::new(Vec::new());
+        image.write_to(&mut jpeg, ImageFormat::Jpeg)?;
+        let jpeg = jpeg.into_inner();
+        // Insert an APP1 Exif segment right after the SOI marker
+        let exif = b"Exif\0\0GPS secret";
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend_from_slice(&[0xFF, 0xE1, 0, exif.len() as u8 + 2]);
+        with_exif.extend_from_slice(exif);
+        with_exif.extend_from_slice(&jpeg[2..]);
+
+        let (output, _) = process(&with_exif, &ProcessOptions::default())?;
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|w| w == needle);
+        assert!(!contains(b"Exif"));
+        assert!(!contains(b"GPS secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        let result =
+            UploadParamsBuilder::new(b"not an image".to_vec()).process(&ProcessOptions::default());
+        assert!(matches!(result, Err(GyazoError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,284 @@
+//! Retention rules that delete images once they are no longer needed
+//!
+//! A [`RetentionPolicy`] is a list of named [`RetentionRule`]s. Running it
+//! lists the whole library, selects every image matched by at least one rule
+//! and not excluded, and deletes the selection one image at a time at a
+//! limited rate.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use tokio::time::MissedTickBehavior;
+
+use crate::cleanup::{self, DeleteOptions, DeleteReport, ImageFilter};
+use crate::{GyazoApi, GyazoError, GyazoImageResponse};
+
+/// Images selected for deletion by a rule
+#[derive(Clone, Debug)]
+pub struct RetentionRule {
+    /// Reported with the images the rule selects
+    pub name: String,
+    pub filter: ImageFilter,
+}
+
+impl RetentionRule {
+    pub fn new(name: impl Into<String>, filter: ImageFilter) -> Self {
+        Self {
+            name: name.into(),
+            filter,
+        }
+    }
+}
+
+/// A set of retention rules
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+    /// Image IDs that are never deleted, whatever the rules say
+    pub exclude: HashSet<String>,
+}
+
+/// Options for [`RetentionPolicy::run`]
+#[derive(Clone, Debug)]
+pub struct RetentionOptions {
+    /// Only report the selected images
+    pub dry_run: bool,
+    /// Minimum time between two `delete_image` requests
+    pub delete_interval: Duration,
+    /// Images requested per page of the listing
+    pub per_page: u32,
+}
+
+impl Default for RetentionOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            delete_interval: Duration::from_millis(500),
+            per_page: 100,
+        }
+    }
+}
+
+/// An image selected by a rule
+#[derive(Clone, Debug)]
+pub struct RetainedImage {
+    pub image: GyazoImageResponse,
+    /// Name of the first rule that matched
+    pub rule: String,
+}
+
+/// Summary of a [`RetentionPolicy::run`]
+#[derive(Debug, Default)]
+pub struct RetentionReport {
+    /// Images selected for deletion, in listing order
+    pub selected: Vec<RetainedImage>,
+    /// IDs of images that matched a rule but are excluded
+    pub excluded: Vec<String>,
+    /// Results of the deletions; empty for a dry run
+    pub deletions: DeleteReport,
+}
+
+impl RetentionPolicy {
+    pub fn rule(mut self, name: impl Into<String>, filter: ImageFilter) -> Self {
+        self.rules.push(RetentionRule::new(name, filter));
+        self
+    }
+
+    pub fn exclude(mut self, image_id: impl Into<String>) -> Self {
+        self.exclude.insert(image_id.into());
+        self
+    }
+
+    /// Name of the first rule matching `image`, ignoring exclusions
+    pub fn matching_rule(&self, image: &GyazoImageResponse) -> Option<&str> {
+        let now = SystemTime::now();
+        self.rules
+            .iter()
+            .find(|rule| rule.filter.matcher(now)(image))
+            .map(|rule| rule.name.as_str())
+    }
+
+    /// Apply the policy to the whole library
+    ///
+    /// Listing errors abort the run before anything is deleted. Deletion
+    /// errors are collected in [`RetentionReport::deletions`]; images that are
+    /// already gone are reported as [`cleanup::DeleteOutcome::AlreadyDeleted`].
+    ///
+    /// Rules with an empty filter fail with [`GyazoError::InvalidInput`],
+    /// since they would select the whole library.
+    pub async fn run<A: GyazoApi + Sync>(
+        &self,
+        api: &A,
+        options: &RetentionOptions,
+    ) -> Result<RetentionReport, GyazoError> {
+        if let Some(rule) = self.rules.iter().find(|rule| rule.filter.is_empty()) {
+            return Err(GyazoError::InvalidInput(format!(
+                "retention rule '{}' has no filter conditions",
+                rule.name
+            )));
+        }
+        let now = SystemTime::now();
+        let matchers: Vec<_> = self
+            .rules
+            .iter()
+            .map(|rule| (rule.name.as_str(), rule.filter.matcher(now)))
+            .collect();
+        let mut report = RetentionReport::default();
+        for image in cleanup::list_all(api, options.per_page).await? {
+            let Some((rule, _)) = matchers.iter().find(|(_, matches)| matches(&image)) else {
+                continue;
+            };
+            if self.exclude.contains(&image.image_id) {
+                report.excluded.push(image.image_id);
+            } else {
+                report.selected.push(RetainedImage {
+                    image,
+                    rule: rule.to_string(),
+                });
+            }
+        }
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        let delete_options = DeleteOptions::default();
+        let mut interval =
+            tokio::time::interval(options.delete_interval.max(Duration::from_millis(1)));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        for selected in &report.selected {
+            interval.tick().await;
+            let image_id = &selected.image.image_id;
+            let result = cleanup::delete_one(api, image_id, &delete_options).await;
+            report.deletions.results.push((image_id.clone(), result));
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::format_utc;
+    use crate::fake::fixtures::upload;
+    use crate::FakeGyazo;
+    use std::time::Instant;
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy::default()
+            .rule(
+                "old screenshots",
+                ImageFilter {
+                    app: Some("Screenshot".to_string()),
+                    older_than: Some(Duration::from_secs(90 * 86_400)),
+                    ..Default::default()
+                },
+            )
+            .rule(
+                "intranet captures",
+                ImageFilter {
+                    source_domain: Some("intranet.example".to_string()),
+                    ..Default::default()
+                },
+            )
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_and_exclusions() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let now = format_utc(SystemTime::now());
+        let old = upload(&fake, "2020-01-01 00:00:00", |params| {
+            params.app("Screenshot")
+        })
+        .await?;
+        let kept = upload(&fake, "2020-01-02 00:00:00", |params| {
+            params.app("Screenshot")
+        })
+        .await?;
+        let recent = upload(&fake, &now, |params| params.app("Screenshot")).await?;
+        let wiki = upload(&fake, &now, |params| {
+            params
+                .app("Chrome")
+                .referer_url("https://wiki.intranet.example/page")
+        })
+        .await?;
+        let intranet = upload(&fake, &now, |params| {
+            params
+                .app("Chrome")
+                .referer_url("https://intranet.example/")
+        })
+        .await?;
+        let lookalike = upload(&fake, &now, |params| {
+            params
+                .app("Chrome")
+                .referer_url("https://notintranet.example/")
+        })
+        .await?;
+
+        let policy = policy().exclude(&kept);
+        let options = RetentionOptions {
+            dry_run: true,
+            per_page: 2,
+            ..Default::default()
+        };
+        let report = policy.run(&fake, &options).await?;
+        let mut selected: Vec<(&str, &str)> = report
+            .selected
+            .iter()
+            .map(|selected| (selected.image.image_id.as_str(), selected.rule.as_str()))
+            .collect();
+        selected.sort();
+        let mut expected = [
+            (old.as_str(), "old screenshots"),
+            (wiki.as_str(), "intranet captures"),
+            (intranet.as_str(), "intranet captures"),
+        ];
+        expected.sort();
+        assert_eq!(selected, expected);
+        assert_eq!(report.excluded, [kept.as_str()]);
+        assert!(report.deletions.results.is_empty());
+        assert_eq!(fake.len(), 6);
+
+        for image_id in [&recent, &lookalike] {
+            let image = fake.get_image(image_id).await?;
+            assert_eq!(policy.matching_rule(&image), None);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_deletes_at_limited_rate() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        for day in 1..=3 {
+            let created_at = format!("2020-01-0{} 00:00:00", day);
+            upload(&fake, &created_at, |params| params.app("Screenshot")).await?;
+        }
+        let kept = upload(&fake, "2020-01-01 00:00:00", |params| params.app("Chrome")).await?;
+
+        let options = RetentionOptions {
+            delete_interval: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let report = policy().run(&fake, &options).await?;
+        assert!(started.elapsed() >= Duration::from_millis(40));
+        assert_eq!(report.deletions.deleted(), 3);
+        assert_eq!(report.deletions.failed().count(), 0);
+        assert_eq!(fake.len(), 1);
+        assert!(fake.get_image(&kept).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_empty_rule_is_rejected() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        upload(&fake, "2020-01-01 00:00:00", |params| {
+            params.app("Screenshot")
+        })
+        .await?;
+        let policy = policy().rule("everything", ImageFilter::default());
+        let result = policy.run(&fake, &Default::default()).await;
+        assert!(matches!(result, Err(GyazoError::InvalidInput(_))));
+        assert_eq!(fake.len(), 1);
+        Ok(())
+    }
+}
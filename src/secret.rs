@@ -0,0 +1,87 @@
+use std::fmt;
+
+use zeroize::Zeroize;
+
+/// Access token that never shows up in `Debug` output and is zeroized on drop
+#[derive(Clone, Default)]
+pub struct SecretToken(String);
+
+impl SecretToken {
+    /// Wrap a raw access token
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    /// Borrow the raw token, e.g. to put it in an `Authorization` header
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the token is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for SecretToken {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretToken([REDACTED])")
+    }
+}
+
+impl From<String> for SecretToken {
+    fn from(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl From<&str> for SecretToken {
+    fn from(token: &str) -> Self {
+        Self(token.to_string())
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<secrecy::SecretString> for SecretToken {
+    fn from(token: secrecy::SecretString) -> Self {
+        use secrecy::ExposeSecret;
+        Self(token.expose_secret().to_string())
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl From<SecretToken> for secrecy::SecretString {
+    fn from(token: SecretToken) -> Self {
+        secrecy::SecretString::from(token.expose_secret().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let token = SecretToken::from("super_secret_token");
+        let debug = format!("{:?}", token);
+        assert_eq!(debug, "SecretToken([REDACTED])");
+        assert!(!debug.contains("super_secret_token"));
+        assert_eq!(token.expose_secret(), "super_secret_token");
+    }
+
+    #[cfg(feature = "secrecy")]
+    #[test]
+    fn test_secrecy_round_trip() {
+        use secrecy::ExposeSecret;
+        let token = SecretToken::from(secrecy::SecretString::from("abc".to_string()));
+        assert_eq!(token.expose_secret(), "abc");
+        let secret: secrecy::SecretString = token.into();
+        assert_eq!(secret.expose_secret(), "abc");
+    }
+}
@@ -0,0 +1,268 @@
+//! Lossless removal of metadata from image files
+//!
+//! Only metadata containers are dropped; the compressed pixel data is copied
+//! byte for byte, so the decoded image is unchanged.
+
+use crate::GyazoError;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG chunks that carry text, timestamps, EXIF or ICC profiles
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 6] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME", b"iCCP"];
+
+/// WebP chunks that carry EXIF, XMP or ICC profiles
+const WEBP_METADATA_CHUNKS: [&[u8; 4]; 3] = [b"EXIF", b"XMP ", b"ICCP"];
+
+/// VP8X flags announcing the chunks in [`WEBP_METADATA_CHUNKS`]
+const VP8X_METADATA_FLAGS: u8 = 0x20 | 0x08 | 0x04;
+
+/// Remove EXIF, XMP, ICC profiles, comments and text chunks from a JPEG, PNG
+/// or WebP file
+pub(crate) fn strip_metadata(data: &[u8]) -> Result<Vec<u8>, GyazoError> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(data)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        strip_png(data)
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        strip_webp(data)
+    } else {
+        Err(GyazoError::InvalidInput(
+            "strip_metadata supports only JPEG, PNG and WebP images".to_string(),
+        ))
+    }
+}
+
+fn malformed(format: &str) -> GyazoError {
+    GyazoError::InvalidInput(format!("malformed {} image", format))
+}
+
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>, GyazoError> {
+    let mut output = data[..2].to_vec();
+    let mut pos = 2;
+    loop {
+        if data.get(pos) != Some(&0xFF) {
+            return Err(malformed("JPEG"));
+        }
+        // Skip fill bytes before the marker code
+        while data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = *data.get(pos + 1).ok_or_else(|| malformed("JPEG"))?;
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            output.extend_from_slice(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        let length = data
+            .get(pos + 2..pos + 4)
+            .map(|bytes| usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+            .ok_or_else(|| malformed("JPEG"))?;
+        let end = pos + 2 + length;
+        if length < 2 || end > data.len() {
+            return Err(malformed("JPEG"));
+        }
+        // Keep JFIF (APP0) and Adobe (APP14), which affect decoding, and drop
+        // every other application segment and comments
+        let is_metadata = marker == 0xFE || ((0xE1..=0xEF).contains(&marker) && marker != 0xEE);
+        if !is_metadata {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+        if marker == 0xDA {
+            // Start of scan: the rest is entropy-coded data
+            output.extend_from_slice(&data[pos..]);
+            return Ok(output);
+        }
+    }
+}
+
+fn strip_png(data: &[u8]) -> Result<Vec<u8>, GyazoError> {
+    let mut output = PNG_SIGNATURE.to_vec();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(|| malformed("PNG"))?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return Err(malformed("PNG"));
+        }
+        if !PNG_METADATA_CHUNKS
+            .iter()
+            .any(|chunk| &header[4..8] == *chunk)
+        {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+        if &header[4..8] == b"IEND" {
+            break;
+        }
+    }
+    Ok(output)
+}
+
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>, GyazoError> {
+    let mut output = data[..12].to_vec();
+    let mut pos = 12;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(|| malformed("WebP"))?;
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if pos + 8 + length > data.len() {
+            return Err(malformed("WebP"));
+        }
+        // Chunks are padded to an even size; tolerate a missing final pad byte
+        let end = (pos + 8 + length + length % 2).min(data.len());
+        let fourcc = &header[..4];
+        if fourcc == b"VP8X" && length >= 1 {
+            let start = output.len();
+            output.extend_from_slice(&data[pos..end]);
+            output[start + 8] &= !VP8X_METADATA_FLAGS;
+        } else if !WEBP_METADATA_CHUNKS.iter().any(|chunk| fourcc == *chunk) {
+            output.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+    let riff_size = u32::try_from(output.len() - 8).map_err(|_| malformed("WebP"))?;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UploadParamsBuilder;
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn encode(format: ImageFormat) -> anyhow::Result<Vec<u8>> {
+        let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 90, 255]));
+        let image = match format {
+            ImageFormat::Jpeg => {
+                image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(image).to_rgb8())
+            }
+            _ => image::DynamicImage::ImageRgba8(image),
+        };
+        let mut output = Cursor::new(Vec::new());
+        image.write_to(&mut output, format)?;
+        Ok(output.into_inner())
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    fn assert_same_pixels(a: &[u8], b: &[u8]) -> anyhow::Result<()> {
+        let a = image::load_from_memory(a)?.to_rgba8();
+        let b = image::load_from_memory(b)?.to_rgba8();
+        assert_eq!(a.dimensions(), b.dimensions());
+        assert!(a.as_raw() == b.as_raw(), "pixel data changed");
+        Ok(())
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn test_strip_jpeg() -> anyhow::Result<()> {
+        let jpeg = encode(ImageFormat::Jpeg)?;
+        let mut tagged = jpeg[..2].to_vec();
+        for (marker, payload) in [
+            (0xE1, &b"Exif\0\0GPS 35.6N 139.7E"[..]),
+            (0xE1, &b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"[..]),
+            (0xE2, &b"ICC_PROFILE\0\x01\x01Pixel 8 Pro"[..]),
+            (0xFE, &b"shot on a company laptop"[..]),
+        ] {
+            tagged.extend_from_slice(&[0xFF, marker]);
+            tagged.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+            tagged.extend_from_slice(payload);
+        }
+        tagged.extend_from_slice(&jpeg[2..]);
+
+        let stripped = strip_metadata(&tagged)?;
+        assert_eq!(stripped, jpeg);
+        for needle in [&b"Exif"[..], b"xmpmeta", b"Pixel 8", b"laptop"] {
+            assert!(!contains(&stripped, needle));
+        }
+        assert_same_pixels(&tagged, &stripped)
+    }
+
+    #[test]
+    fn test_strip_png() -> anyhow::Result<()> {
+        let png = encode(ImageFormat::Png)?;
+        // Insert a text chunk right after IHDR
+        let ihdr_end = PNG_SIGNATURE.len() + 8 + 13 + 4;
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend_from_slice(b"Comment\0GPS 35.6N 139.7E");
+        let mut tagged = png[..ihdr_end].to_vec();
+        tagged.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+        tagged.extend_from_slice(&chunk);
+        tagged.extend_from_slice(&crc32(&chunk).to_be_bytes());
+        tagged.extend_from_slice(&png[ihdr_end..]);
+
+        let stripped = strip_metadata(&tagged)?;
+        assert_eq!(stripped, png);
+        assert!(!contains(&stripped, b"GPS"));
+        assert_same_pixels(&tagged, &stripped)
+    }
+
+    #[test]
+    fn test_strip_webp() -> anyhow::Result<()> {
+        let webp = encode(ImageFormat::WebP)?;
+        let image_chunk = &webp[12..];
+        let mut vp8x = vec![0x08 | 0x10, 0, 0, 0];
+        vp8x.extend_from_slice(&15u32.to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&7u32.to_le_bytes()[..3]);
+        let exif = b"Exif\0\0GPS 35.6N 139.7E";
+
+        // Extended file: VP8X header, the encoded VP8L chunk, then EXIF
+        let mut tagged = b"RIFF\0\0\0\0WEBP".to_vec();
+        for (fourcc, payload) in [(&b"VP8X"[..], &vp8x[..]), (b"EXIF", &exif[..])] {
+            tagged.extend_from_slice(fourcc);
+            tagged.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            tagged.extend_from_slice(payload);
+            if payload.len() % 2 == 1 {
+                tagged.push(0);
+            }
+            if fourcc == b"VP8X" {
+                tagged.extend_from_slice(image_chunk);
+            }
+        }
+        let riff_size = (tagged.len() - 8) as u32;
+        tagged[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+        let stripped = strip_metadata(&tagged)?;
+        assert!(!contains(&stripped, b"GPS"));
+        assert_eq!(stripped[20] & VP8X_METADATA_FLAGS, 0);
+        assert_eq!(
+            u32::from_le_bytes([stripped[4], stripped[5], stripped[6], stripped[7]]) as usize,
+            stripped.len() - 8
+        );
+        assert_same_pixels(&tagged, &stripped)
+    }
+
+    #[test]
+    fn test_builder_strip_metadata() -> anyhow::Result<()> {
+        let png = encode(ImageFormat::Png)?;
+        let params = UploadParamsBuilder::new(png.clone())
+            .strip_metadata(true)
+            .build()?;
+        assert_eq!(params.imagedata, png);
+
+        let result = UploadParamsBuilder::new(b"GIF89a".to_vec())
+            .strip_metadata(true)
+            .build();
+        assert!(matches!(result, Err(GyazoError::InvalidInput(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+//! Mirror a local folder of screenshots to Gyazo
+//!
+//! [`Syncer`] uploads new image files from a directory, remembers which file
+//! became which image in a JSON state file, and optionally deletes remote
+//! images when their local file is removed. Files are identified by the
+//! SHA-256 of their contents, so renaming a file or restarting the syncer
+//! never uploads the same bytes twice.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::dedup::sha256_hex;
+use crate::fs_util::{from_json, to_json_pretty, write_atomically_async};
+use crate::{GyazoApi, GyazoError, UploadParamsBuilder};
+
+/// Options for [`Syncer`]
+#[derive(Clone, Debug)]
+pub struct SyncOptions {
+    /// Delete remote images whose local file was removed
+    pub delete_remote: bool,
+    /// File extensions to upload, compared case-insensitively
+    pub extensions: Vec<String>,
+    /// `app` metadata sent with every upload
+    pub app: Option<String>,
+    /// `access_policy` sent with every upload, `anyone` or `only_me`
+    pub access_policy: Option<String>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            delete_remote: false,
+            extensions: ["png", "jpg", "jpeg", "gif", "webp"]
+                .map(String::from)
+                .to_vec(),
+            app: None,
+            access_policy: None,
+        }
+    }
+}
+
+/// Remote image a local file was uploaded as
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SyncedFile {
+    pub image_id: String,
+    pub permalink_url: String,
+    /// Hex-encoded SHA-256 of the file contents
+    pub sha256: String,
+}
+
+/// Mapping from file name to uploaded image, persisted between runs
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SyncState {
+    pub files: BTreeMap<String, SyncedFile>,
+}
+
+impl SyncState {
+    fn find_by_hash(&self, sha256: &str) -> Option<&SyncedFile> {
+        self.files.values().find(|file| file.sha256 == sha256)
+    }
+}
+
+/// Summary of one [`Syncer::sync_once`] pass
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Files uploaded to Gyazo
+    pub uploaded: usize,
+    /// New or changed files whose contents were already uploaded
+    pub deduplicated: usize,
+    /// Files already in sync
+    pub unchanged: usize,
+    /// Local files removed since the last pass
+    pub removed: usize,
+    /// Remote images deleted
+    pub deleted: usize,
+    /// Files that could not be read, uploaded or have their old remote image
+    /// deleted, by file name; they are retried on the next pass
+    pub failed: Vec<(String, GyazoError)>,
+}
+
+/// Uploads new files from a directory to Gyazo
+pub struct Syncer<A> {
+    api: A,
+    dir: PathBuf,
+    state_path: PathBuf,
+    options: SyncOptions,
+    state: SyncState,
+}
+
+impl<A: GyazoApi> Syncer<A> {
+    /// Create a syncer for the files directly inside `dir`
+    ///
+    /// The state is loaded from `state_path` if it exists.
+    pub async fn new(
+        api: A,
+        dir: impl Into<PathBuf>,
+        state_path: impl Into<PathBuf>,
+        options: SyncOptions,
+    ) -> Result<Self, GyazoError> {
+        let state_path = state_path.into();
+        let state = match fs::read(&state_path).await {
+            Ok(contents) => from_json(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SyncState::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            api,
+            dir: dir.into(),
+            state_path,
+            options,
+            state,
+        })
+    }
+
+    pub fn state(&self) -> &SyncState {
+        &self.state
+    }
+
+    /// Scan the directory once and bring Gyazo in line with it
+    ///
+    /// A file that fails is recorded in [`SyncReport::failed`] and does not
+    /// stop the others. Only failing to list the directory or to save the
+    /// state returns an error.
+    pub async fn sync_once(&mut self) -> Result<SyncReport, GyazoError> {
+        let mut report = SyncReport::default();
+        let files = self.scan().await?;
+        let present: HashSet<&String> = files.iter().map(|(name, _)| name).collect();
+        let removed: Vec<String> = self
+            .state
+            .files
+            .keys()
+            .filter(|name| !present.contains(name))
+            .cloned()
+            .collect();
+
+        for (name, path) in &files {
+            let imagedata = match fs::read(path).await {
+                Ok(imagedata) => imagedata,
+                Err(error) => {
+                    report.failed.push((name.clone(), error.into()));
+                    continue;
+                }
+            };
+            let sha256 = sha256_hex(&imagedata);
+            let previous = self.state.files.get(name);
+            if previous.is_some_and(|file| file.sha256 == sha256) {
+                report.unchanged += 1;
+                continue;
+            }
+            let previous = previous.map(|file| file.image_id.clone());
+            let synced = match self.state.find_by_hash(&sha256) {
+                Some(existing) => {
+                    report.deduplicated += 1;
+                    existing.clone()
+                }
+                None => match self.upload(path, imagedata, sha256).await {
+                    Ok(synced) => {
+                        report.uploaded += 1;
+                        synced
+                    }
+                    Err(error) => {
+                        report.failed.push((name.clone(), error));
+                        continue;
+                    }
+                },
+            };
+            self.state.files.insert(name.clone(), synced);
+            // Save right away so a restart never uploads this file again
+            self.save_state().await?;
+            if let Some(previous) = previous {
+                if let Err(error) = self.delete_if_orphaned(&previous, &mut report).await {
+                    report.failed.push((name.clone(), error));
+                }
+            }
+        }
+
+        for name in removed {
+            if let Some(file) = self.state.files.remove(&name) {
+                report.removed += 1;
+                self.save_state().await?;
+                if let Err(error) = self.delete_if_orphaned(&file.image_id, &mut report).await {
+                    report.failed.push((name, error));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sync every `interval` until listing the directory or saving the
+    /// state fails
+    ///
+    /// Files that fail are retried on the next tick.
+    pub async fn watch(&mut self, interval: Duration) -> Result<(), GyazoError> {
+        loop {
+            self.sync_once().await?;
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn scan(&self) -> Result<Vec<(String, PathBuf)>, GyazoError> {
+        let mut files = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_file() {
+                continue;
+            }
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let extension = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default();
+            if self
+                .options
+                .extensions
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(extension))
+            {
+                files.push((name.to_string(), path));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    async fn upload(
+        &self,
+        path: &Path,
+        imagedata: Vec<u8>,
+        sha256: String,
+    ) -> Result<SyncedFile, GyazoError> {
+        let mut builder = UploadParamsBuilder::new(imagedata);
+        if let Some(title) = path.file_stem().and_then(|stem| stem.to_str()) {
+            builder = builder.title(title);
+        }
+        let modified = fs::metadata(path).await?.modified().ok();
+        if let Some(created_at) = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+            builder = builder.created_at(created_at.as_secs().to_string());
+        }
+        if let Some(app) = &self.options.app {
+            builder = builder.app(app);
+        }
+        if let Some(access_policy) = &self.options.access_policy {
+            builder = builder.access_policy(access_policy)?;
+        }
+        let uploaded = self.api.upload_image(builder.build()?).await?;
+        Ok(SyncedFile {
+            image_id: uploaded.image_id,
+            permalink_url: uploaded.permalink_url,
+            sha256,
+        })
+    }
+
+    /// Delete a remote image once no local file refers to it
+    async fn delete_if_orphaned(
+        &self,
+        image_id: &str,
+        report: &mut SyncReport,
+    ) -> Result<(), GyazoError> {
+        let referenced = self
+            .state
+            .files
+            .values()
+            .any(|file| file.image_id == image_id);
+        if !self.options.delete_remote || referenced {
+            return Ok(());
+        }
+        match self.api.delete_image(image_id).await {
+            Ok(_) | Err(GyazoError::NotFound) => {
+                report.deleted += 1;
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn save_state(&self) -> Result<(), GyazoError> {
+        let contents = to_json_pretty(&self.state)?;
+        Ok(write_atomically_async(&self.state_path, &contents).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::png_header;
+    use crate::FakeGyazo;
+
+    #[tokio::test]
+    async fn test_sync() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let dir = tempfile::tempdir()?;
+        let state_path = dir.path().join("state.json");
+        let options = SyncOptions {
+            delete_remote: true,
+            ..Default::default()
+        };
+        std::fs::write(dir.path().join("a.png"), png_header(1, 1, &[1]))?;
+        std::fs::write(dir.path().join("b.PNG"), png_header(1, 1, &[2]))?;
+        std::fs::write(dir.path().join("notes.txt"), [3])?;
+
+        let mut syncer = Syncer::new(&fake, dir.path(), &state_path, options.clone()).await?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.uploaded, 2);
+        assert_eq!(fake.len(), 2);
+        let a = syncer.state().files["a.png"].clone();
+        let image = fake.get_image(&a.image_id).await?;
+        assert_eq!(image.metadata.title, Some("a".to_string()));
+
+        // Restarting with the saved state uploads nothing
+        let mut syncer = Syncer::new(&fake, dir.path(), &state_path, options).await?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.unchanged, 2);
+        assert_eq!(report.uploaded, 0);
+
+        // Renaming is deduplicated by content and keeps the remote image
+        std::fs::rename(dir.path().join("a.png"), dir.path().join("c.png"))?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.deduplicated, 1);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.deleted, 0);
+        assert_eq!(syncer.state().files["c.png"].image_id, a.image_id);
+        assert_eq!(fake.len(), 2);
+
+        // Removing the file deletes the remote image
+        std::fs::remove_file(dir.path().join("c.png"))?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.deleted, 1);
+        assert_eq!(fake.len(), 1);
+        assert!(fake.get_image(&a.image_id).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_keeps_remote_by_default() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let dir = tempfile::tempdir()?;
+        let state_path = dir.path().join("state.json");
+        std::fs::write(dir.path().join("a.png"), png_header(1, 1, &[1]))?;
+
+        let mut syncer =
+            Syncer::new(&fake, dir.path(), &state_path, SyncOptions::default()).await?;
+        syncer.sync_once().await?;
+        std::fs::write(dir.path().join("a.png"), png_header(1, 1, &[4]))?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.uploaded, 1);
+        assert_eq!(report.deleted, 0);
+        assert_eq!(fake.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_continues_past_failing_files() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let dir = tempfile::tempdir()?;
+        let state_path = dir.path().join("state.json");
+        let oversized = png_header(1, 1, &vec![0; 10 * 1024 * 1024]);
+        std::fs::write(dir.path().join("a.png"), oversized)?;
+        std::fs::write(dir.path().join("b.png"), png_header(1, 1, &[1]))?;
+
+        let mut syncer =
+            Syncer::new(&fake, dir.path(), &state_path, SyncOptions::default()).await?;
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.uploaded, 1);
+        assert!(matches!(
+            report.failed[..],
+            [(ref name, GyazoError::InvalidInput(_))] if name == "a.png"
+        ));
+        assert!(!syncer.state().files.contains_key("a.png"));
+        assert!(syncer.state().files.contains_key("b.png"));
+
+        // The failing file is tried again on the next pass
+        let report = syncer.sync_once().await?;
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.failed.len(), 1);
+        Ok(())
+    }
+}
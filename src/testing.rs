@@ -0,0 +1,401 @@
+//! Local fake Gyazo HTTP server for integration tests
+//!
+//! Enabled with the `test-server` feature. Point
+//! [`GyazoClientOptions::base_url`](crate::GyazoClientOptions) and
+//! `upload_url` at [`FakeServer::url`] to run end-to-end flows offline.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::{Multipart, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use crate::{
+    FakeGyazo, GyazoApi, GyazoError, GyazoImageResponse, ListImagesParams, UploadParamsBuilder,
+};
+
+/// Failure injected into the next request handled by a [`FakeServer`]
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Respond with `429 Too Many Requests` and a `Retry-After` header
+    RateLimited { retry_after: Duration },
+    /// Respond with `500 Internal Server Error`
+    InternalServerError,
+    /// Wait before handling the request normally
+    Delay(Duration),
+}
+
+/// Real HTTP server backed by a [`FakeGyazo`] store
+///
+/// Implements upload, get, list (with `X-Total-Count`, `X-Current-Page` and
+/// `X-Per-Page` headers), delete, oembed and the current user. The server stops when dropped.
+#[derive(Debug)]
+pub struct FakeServer {
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Debug, Default)]
+struct ServerState {
+    fake: FakeGyazo,
+    faults: Mutex<VecDeque<Fault>>,
+}
+
+impl FakeServer {
+    /// Start a server on a random local port
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(ServerState::default());
+        let app = Router::new()
+            .route("/api/upload", post(upload_image))
+            .route("/api/images", get(list_images))
+            .route("/api/images/:image_id", get(get_image).delete(delete_image))
+            .route("/api/oembed", get(get_oembed))
+            .route("/api/users/me", get(get_user))
+            .layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+            .with_state(state.clone());
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Base URL of the server, usable as both `base_url` and `upload_url`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Store backing the server, for seeding data and inspecting uploads
+    pub fn fake(&self) -> &FakeGyazo {
+        &self.state.fake
+    }
+
+    /// Queue a fault for the next request; faults are consumed in order
+    pub fn inject(&self, fault: Fault) {
+        self.state
+            .faults
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(fault);
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn inject_faults(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !request.headers().contains_key(header::AUTHORIZATION) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let fault = state
+        .faults
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .pop_front();
+    match fault {
+        Some(Fault::RateLimited { retry_after }) => {
+            let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(retry_after.as_secs()),
+            );
+            response
+        }
+        Some(Fault::InternalServerError) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            next.run(request).await
+        }
+        None => next.run(request).await,
+    }
+}
+
+async fn upload_image(
+    State(state): State<Arc<ServerState>>,
+    mut multipart: Multipart,
+) -> Result<Json<Value>, StatusCode> {
+    let mut imagedata = None;
+    let mut fields = Vec::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let bytes = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if name == "imagedata" {
+            imagedata = Some(bytes.to_vec());
+        } else {
+            fields.push((name, String::from_utf8_lossy(&bytes).into_owned()));
+        }
+    }
+    let mut builder = UploadParamsBuilder::new(imagedata.ok_or(StatusCode::BAD_REQUEST)?);
+    for (name, value) in fields {
+        builder = match name.as_str() {
+            "access_policy" => builder.access_policy(value),
+            "metadata_is_public" => builder.metadata_is_public(value),
+            "referer_url" => Ok(builder.referer_url(value)),
+            "app" => Ok(builder.app(value)),
+            "title" => Ok(builder.title(value)),
+            "desc" => Ok(builder.desc(value)),
+            "created_at" => Ok(builder.created_at(value)),
+            "collection_id" => Ok(builder.collection_id(value)),
+            _ => Ok(builder),
+        }
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    }
+    let params = builder.build().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let uploaded = state.fake.upload_image(params).await.map_err(status_for)?;
+    Ok(Json(json!({
+        "image_id": uploaded.image_id,
+        "permalink_url": uploaded.permalink_url,
+        "thumb_url": uploaded.thumb_url,
+        "url": uploaded.url,
+        "type": uploaded.image_type,
+    })))
+}
+
+async fn list_images(
+    State(state): State<Arc<ServerState>>,
+    Query(params): Query<ListQuery>,
+) -> Result<(HeaderMap, Json<Value>), StatusCode> {
+    let page = state
+        .fake
+        .list_images_page(&ListImagesParams {
+            page: params.page,
+            per_page: params.per_page,
+        })
+        .await
+        .map_err(status_for)?;
+    let mut headers = HeaderMap::new();
+    let pagination = [
+        ("X-Total-Count", page.total_count),
+        ("X-Current-Page", page.current_page.map(u64::from)),
+        ("X-Per-Page", page.per_page.map(u64::from)),
+    ];
+    for (name, value) in pagination {
+        if let Some(value) = value {
+            headers.insert(name, HeaderValue::from(value));
+        }
+    }
+    Ok((
+        headers,
+        Json(Value::Array(page.images.iter().map(image_json).collect())),
+    ))
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+async fn get_user(State(state): State<Arc<ServerState>>) -> Result<Json<Value>, StatusCode> {
+    let user = state.fake.get_user().await.map_err(status_for)?;
+    Ok(Json(json!({ "user": user.user })))
+}
+
+async fn get_image(
+    State(state): State<Arc<ServerState>>,
+    Path(image_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let image = state.fake.get_image(&image_id).await.map_err(status_for)?;
+    Ok(Json(image_json(&image)))
+}
+
+async fn delete_image(
+    State(state): State<Arc<ServerState>>,
+    Path(image_id): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let deleted = state
+        .fake
+        .delete_image(&image_id)
+        .await
+        .map_err(status_for)?;
+    Ok(Json(json!({
+        "image_id": deleted.image_id,
+        "type": deleted.image_type,
+    })))
+}
+
+#[derive(Deserialize)]
+struct OembedQuery {
+    url: String,
+}
+
+async fn get_oembed(
+    State(state): State<Arc<ServerState>>,
+    Query(query): Query<OembedQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let oembed = state
+        .fake
+        .get_oembed(&query.url)
+        .await
+        .map_err(status_for)?;
+    Ok(Json(json!({
+        "version": oembed.version,
+        "type": oembed.image_type,
+        "provider_name": oembed.provider_name,
+        "provider_url": oembed.provider_url,
+        "url": oembed.url,
+        "width": oembed.width,
+        "height": oembed.height,
+        "title": oembed.title,
+        "thumbnail_url": oembed.thumbnail_url,
+    })))
+}
+
+fn image_json(image: &GyazoImageResponse) -> Value {
+    json!({
+        "image_id": image.image_id,
+        "permalink_url": image.permalink_url,
+        "thumb_url": image.thumb_url,
+        "url": image.url,
+        "type": image.image_type,
+        "created_at": image.created_at,
+        "metadata": {
+            "app": image.metadata.app,
+            "title": image.metadata.title,
+            "url": image.metadata.url,
+            "desc": image.metadata.desc,
+        },
+        "ocr": image.ocr.as_ref().map(|ocr| json!({
+            "locale": ocr.locale,
+            "description": ocr.description,
+        })),
+    })
+}
+
+fn status_for(error: GyazoError) -> StatusCode {
+    match error {
+        GyazoError::NotFound => StatusCode::NOT_FOUND,
+        GyazoError::InvalidUrl(_) | GyazoError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_format::png_header;
+    use crate::{GyazoClient, GyazoClientOptions};
+
+    fn client_for(server: &FakeServer) -> GyazoClient {
+        GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url()),
+            upload_url: Some(server.url()),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_end_to_end() -> anyhow::Result<()> {
+        let server = FakeServer::start().await?;
+        let client = client_for(&server);
+
+        let params = UploadParamsBuilder::new(png_header(1, 1, &[]))
+            .title("test image")
+            .build()?;
+        let uploaded = client.upload_image(params).await?;
+        assert_eq!(
+            server.fake().image_data(&uploaded.image_id),
+            Some(png_header(1, 1, &[]))
+        );
+
+        let image = client.get_image(&uploaded.image_id).await?;
+        assert_eq!(image.metadata.title, Some("test image".to_string()));
+
+        let images = client.list_images().await?;
+        assert_eq!(images.len(), 1);
+
+        let user = client.get_user().await?;
+        assert_eq!(user.user.name, "fake");
+
+        let oembed = client.get_oembed(&uploaded.permalink_url).await?;
+        assert_eq!(oembed.version, "1.0");
+
+        client.delete_image(&uploaded.image_id).await?;
+        assert!(matches!(
+            client.get_image(&uploaded.image_id).await,
+            Err(GyazoError::NotFound)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_pagination_headers() -> anyhow::Result<()> {
+        let server = FakeServer::start().await?;
+        for _ in 0..3 {
+            let params = UploadParamsBuilder::new(png_header(1, 1, &[])).build()?;
+            server.fake().upload_image(params).await?;
+        }
+        let response = reqwest::Client::new()
+            .get(format!("{}/api/images?page=2&per_page=2", server.url()))
+            .bearer_auth("fake_token")
+            .send()
+            .await?;
+        assert_eq!(response.headers()["X-Total-Count"], "3");
+        assert_eq!(response.headers()["X-Current-Page"], "2");
+        assert_eq!(response.headers()["X-Per-Page"], "2");
+        let body: Vec<Value> = response.json().await?;
+        assert_eq!(body.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection() -> anyhow::Result<()> {
+        let server = FakeServer::start().await?;
+        let client = client_for(&server);
+
+        server.inject(Fault::RateLimited {
+            retry_after: Duration::from_secs(30),
+        });
+        server.inject(Fault::InternalServerError);
+        server.inject(Fault::Delay(Duration::from_millis(50)));
+
+        assert!(matches!(
+            client.list_images().await,
+            Err(GyazoError::RateLimitExceeded)
+        ));
+        assert!(matches!(
+            client.list_images().await,
+            Err(GyazoError::InternalServerError)
+        ));
+        let started = std::time::Instant::now();
+        assert!(client.list_images().await?.is_empty());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_requires_authorization() -> anyhow::Result<()> {
+        let server = FakeServer::start().await?;
+        let response = reqwest::get(format!("{}/api/images", server.url())).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+//! Polling for newly uploaded images
+//!
+//! Gyazo has no webhooks, so [`watch_new_images`] polls the first page of the
+//! image list and yields the images it has not seen before. What has been seen
+//! is tracked by a high-water mark on `created_at`, which can be persisted so a
+//! restarted watcher resumes where it stopped.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::date::sort_key;
+use crate::fs_util::{from_json, to_json_pretty, write_atomically_async};
+use crate::{GyazoApi, GyazoError, GyazoImageResponse, ListImagesParams};
+
+/// Options for [`watch_new_images`]
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// Time between two polls
+    pub interval: Duration,
+    /// Images requested per poll; more uploads than this between two polls
+    /// are missed
+    pub per_page: u32,
+    /// File keeping the high-water mark across restarts
+    pub cursor_path: Option<PathBuf>,
+    /// Longest wait between polls after repeated rate limiting, server errors
+    /// or network failures
+    pub max_backoff: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            per_page: 20,
+            cursor_path: None,
+            max_backoff: Duration::from_secs(600),
+        }
+    }
+}
+
+/// Newest `created_at` seen so far, in UTC, and the images created at that
+/// time
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Cursor {
+    created_at: String,
+    image_ids: Vec<String>,
+}
+
+impl Cursor {
+    fn is_new(&self, image: &GyazoImageResponse) -> bool {
+        let created_at = sort_key(&image.created_at);
+        created_at > self.created_at
+            || (created_at == self.created_at && !self.image_ids.contains(&image.image_id))
+    }
+
+    fn advance<'a>(&mut self, images: impl IntoIterator<Item = &'a GyazoImageResponse>) {
+        for image in images {
+            let created_at = sort_key(&image.created_at);
+            if created_at > self.created_at {
+                self.created_at = created_at;
+                self.image_ids = vec![image.image_id.clone()];
+            } else if created_at == self.created_at && !self.image_ids.contains(&image.image_id) {
+                self.image_ids.push(image.image_id.clone());
+            }
+        }
+    }
+}
+
+/// Stream the images uploaded after the watcher started
+///
+/// The first poll only records what already exists, unless a persisted
+/// cursor is found. New images are yielded oldest first. Rate limiting,
+/// server errors and network failures are retried with exponential backoff;
+/// any other error is yielded and ends the stream. Images count as seen once
+/// they are polled, so images polled but not consumed before a restart are
+/// not yielded again.
+pub fn watch_new_images<A: GyazoApi>(
+    api: A,
+    options: WatchOptions,
+) -> impl Stream<Item = Result<GyazoImageResponse, GyazoError>> {
+    let watcher = Watcher {
+        api,
+        options,
+        cursor: None,
+        loaded: false,
+        pending: VecDeque::new(),
+        delay: Duration::ZERO,
+        finished: false,
+    };
+    stream::unfold(watcher, |mut watcher| async move {
+        let item = watcher.next().await?;
+        Some((item, watcher))
+    })
+}
+
+struct Watcher<A> {
+    api: A,
+    options: WatchOptions,
+    cursor: Option<Cursor>,
+    /// Whether the persisted cursor has been read
+    loaded: bool,
+    pending: VecDeque<GyazoImageResponse>,
+    /// Wait before the next poll
+    delay: Duration,
+    finished: bool,
+}
+
+impl<A: GyazoApi> Watcher<A> {
+    async fn next(&mut self) -> Option<Result<GyazoImageResponse, GyazoError>> {
+        loop {
+            if let Some(image) = self.pending.pop_front() {
+                return Some(Ok(image));
+            }
+            if self.finished {
+                return None;
+            }
+            tokio::time::sleep(self.delay).await;
+            match self.poll().await {
+                Ok(()) => self.delay = self.options.interval,
+                Err(
+                    GyazoError::RateLimitExceeded
+                    | GyazoError::InternalServerError
+                    | GyazoError::RequestFailed(_),
+                ) => {
+                    let max_backoff = self.options.max_backoff.max(self.options.interval);
+                    self.delay = (self.delay.max(self.options.interval) * 2).min(max_backoff);
+                }
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<(), GyazoError> {
+        if !self.loaded {
+            if let Some(path) = &self.options.cursor_path {
+                self.cursor = load_cursor(path).await?;
+            }
+            self.loaded = true;
+        }
+        let page = self
+            .api
+            .list_images_page(&ListImagesParams {
+                page: Some(1),
+                per_page: Some(self.options.per_page),
+            })
+            .await?;
+        let cursor = match &mut self.cursor {
+            Some(cursor) => {
+                // The list is newest first
+                let mut new: Vec<_> = page
+                    .images
+                    .into_iter()
+                    .rev()
+                    .filter(|image| cursor.is_new(image))
+                    .collect();
+                if new.is_empty() {
+                    return Ok(());
+                }
+                new.sort_by_cached_key(|image| sort_key(&image.created_at));
+                cursor.advance(&new);
+                self.pending.extend(new);
+                cursor
+            }
+            None => {
+                let mut cursor = Cursor::default();
+                cursor.advance(&page.images);
+                self.cursor.insert(cursor)
+            }
+        };
+        if let Some(path) = &self.options.cursor_path {
+            save_cursor(path, cursor).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn load_cursor(path: &Path) -> Result<Option<Cursor>, GyazoError> {
+    match fs::read(path).await {
+        Ok(contents) => Ok(Some(from_json(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn save_cursor(path: &Path, cursor: &Cursor) -> Result<(), GyazoError> {
+    let contents = to_json_pretty(cursor)?;
+    Ok(write_atomically_async(path, &contents).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::fixtures::upload;
+    use crate::{FakeGyazo, GyazoClient, GyazoClientOptions};
+    use futures_util::StreamExt;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_watch_new_images() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let dir = tempfile::tempdir()?;
+        let options = WatchOptions {
+            interval: Duration::from_millis(10),
+            cursor_path: Some(dir.path().join("cursor.json")),
+            ..Default::default()
+        };
+        upload(&fake, "2024-01-01 00:00:00", |params| {
+            params.title("existing")
+        })
+        .await?;
+
+        let mut watcher = Box::pin(watch_new_images(&fake, options.clone()));
+        let (titles, uploaded) = tokio::join!(
+            async {
+                let mut titles = Vec::new();
+                for _ in 0..2 {
+                    let image = watcher.next().await.transpose()?;
+                    titles.push(image.and_then(|image| image.metadata.title));
+                }
+                Ok::<_, GyazoError>(titles)
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                upload(&fake, "2024-01-01 00:00:00", |params| {
+                    params.title("same second")
+                })
+                .await?;
+                upload(&fake, "2024-01-02T00:00:00+09:00", |params| {
+                    params.title("later")
+                })
+                .await
+            }
+        );
+        uploaded?;
+        assert_eq!(
+            titles?,
+            [Some("same second".to_string()), Some("later".to_string())]
+        );
+        drop(watcher);
+
+        // A new watcher resumes from the persisted cursor
+        upload(&fake, "2024-01-03 00:00:00", |params| {
+            params.title("while stopped")
+        })
+        .await?;
+        let mut watcher = Box::pin(watch_new_images(&fake, options));
+        let image = watcher.next().await.transpose()?;
+        assert_eq!(
+            image.and_then(|image| image.metadata.title),
+            Some("while stopped".to_string())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_compares_created_at_in_utc() -> anyhow::Result<()> {
+        let fake = FakeGyazo::new();
+        let options = WatchOptions {
+            interval: Duration::from_millis(10),
+            ..Default::default()
+        };
+        // 2024-01-01 00:00:00 UTC
+        upload(&fake, "2024-01-01T09:00:00+09:00", |params| {
+            params.title("existing")
+        })
+        .await?;
+
+        let mut watcher = Box::pin(watch_new_images(&fake, options));
+        let (titles, uploaded) = tokio::join!(
+            async {
+                let mut titles = Vec::new();
+                for _ in 0..2 {
+                    let image = watcher.next().await.transpose()?;
+                    titles.push(image.and_then(|image| image.metadata.title));
+                }
+                Ok::<_, GyazoError>(titles)
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                // 2023-12-31 23:30:00 UTC, before the existing image
+                upload(&fake, "2024-01-01T09:30:00+10:00", |params| {
+                    params.title("older")
+                })
+                .await?;
+                upload(&fake, "2024-01-01 23:30:00", |params| params.title("UTC")).await?;
+                // 2024-01-01 23:00:00 UTC
+                upload(&fake, "2024-01-02T08:00:00+09:00", |params| {
+                    params.title("JST")
+                })
+                .await
+            }
+        );
+        uploaded?;
+        assert_eq!(titles?, [Some("JST".to_string()), Some("UTC".to_string())]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_backs_off_on_rate_limit() -> anyhow::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let image = |id: &str, created_at: &str| {
+            format!(
+                r#"{{"image_id": "{}", "type": "png", "created_at": "{}", "metadata": {{}}, "ocr": null}}"#,
+                id, created_at
+            )
+        };
+        let baseline = server
+            .mock("GET", "/api/images")
+            .match_query(mockito::Matcher::Any)
+            .with_header("content-type", "application/json")
+            .with_body(format!("[{}]", image("a", "2024-01-01 00:00:00")))
+            .expect(1)
+            .create_async()
+            .await;
+        let rate_limited = server
+            .mock("GET", "/api/images")
+            .match_query(mockito::Matcher::Any)
+            .with_status(429)
+            .expect(2)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/images")
+            .match_query(mockito::Matcher::Any)
+            .with_header("content-type", "application/json")
+            .with_body(format!(
+                "[{}, {}]",
+                image("b", "2024-01-02 00:00:00"),
+                image("a", "2024-01-01 00:00:00")
+            ))
+            .create_async()
+            .await;
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url()),
+            ..Default::default()
+        });
+
+        let started = Instant::now();
+        let mut watcher = Box::pin(client.watch_new_images(Duration::from_millis(10)));
+        let image = watcher.next().await.transpose()?;
+        assert_eq!(image.map(|image| image.image_id), Some("b".to_string()));
+        // 10ms after the baseline, then 20ms and 40ms of backoff
+        assert!(started.elapsed() >= Duration::from_millis(70));
+        baseline.assert_async().await;
+        rate_limited.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_ends_on_unauthorized() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/images")
+            .match_query(mockito::Matcher::Any)
+            .with_status(401)
+            .create_async()
+            .await;
+        let client = GyazoClient::new(GyazoClientOptions {
+            access_token: "fake_token".into(),
+            base_url: Some(server.url()),
+            ..Default::default()
+        });
+        let items: Vec<_> = client
+            .watch_new_images(Duration::from_millis(10))
+            .collect()
+            .await;
+        assert!(matches!(items[..], [Err(GyazoError::Unauthorized)]));
+    }
+}